@@ -0,0 +1,304 @@
+// Timings for the hit tests every ray goes through, on a made up scene of a thousand spheres and triangles so there's
+// a baseline to compare changes against. Run with `cargo bench`.
+//
+// To see what the simd feature is worth, save a run of the plain math and compare a simd run against it:
+//     cargo bench -- --save-baseline scalar
+//     cargo bench --features simd -- --baseline scalar
+#![allow(clippy::needless_return)]
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use raytracer::geometry::{
+    sphere_intersect, triangle_hit, Albedo, Material, MaterialType, PreparedTriangle, RayHit,
+    Sphere, Triangle, World,
+};
+use raytracer::rng::Rng;
+use raytracer::scene::{build_accel, find_closest_hit, is_occluded, Accel};
+use raytracer::vec_math::{cross, mag, norm, vec, Ray, Vec3};
+
+const GRAY: Material = Material::new(MaterialType::Matte {
+    albedo: Albedo::Solid(Vec3 {
+        x: 0.5,
+        y: 0.5,
+        z: 0.5,
+    }),
+});
+
+/// How many objects of each kind go in the scene
+const SPHERES: usize = 500;
+const TRIANGLES: usize = 500;
+/// How many triangles go in the scene made of nothing else, like a loaded mesh
+const MESH_TRIANGLES: usize = 5000;
+/// How many spheres go in the scene made of nothing else, for timing the one pass over them with no BVH or grid
+const MANY_SPHERES: usize = 10_000;
+/// How many rays are fired each time round, so one lucky ray doesn't decide a timing
+const RAYS: usize = 256;
+
+/// A point somewhere in the box the scene fills, from -50 to 50 on each axis
+fn point(rng: &mut Rng) -> Vec3 {
+    return vec(
+        rng.next_f32() * 100.0 - 50.0,
+        rng.next_f32() * 100.0 - 50.0,
+        rng.next_f32() * 100.0 - 50.0,
+    );
+}
+
+fn spheres(rng: &mut Rng, count: usize) -> Vec<Sphere> {
+    return (0..count)
+        .map(|i| Sphere {
+            center: point(rng),
+            velocity: vec(0.0, 0.0, 0.0),
+            radius: 0.5 + rng.next_f32() * 1.5,
+            inverted: false,
+            mat: GRAY,
+            id: (i % 100) as i8,
+        })
+        .collect();
+}
+
+fn triangles(rng: &mut Rng, count: usize) -> Vec<PreparedTriangle> {
+    return (0..count)
+        .map(|i| {
+            let a = point(rng);
+            PreparedTriangle::new(Triangle {
+                a,
+                b: a + rng.in_unit_sphere() * 3.0,
+                c: a + rng.in_unit_sphere() * 3.0,
+                vertex_normals: None,
+                uvs: None,
+                double_sided: true,
+                cull_backfaces: false,
+                mat: GRAY,
+                id: (i % 100) as i8,
+            })
+        })
+        .collect();
+}
+
+/// Rays from in front of the scene towards points spread through it, so some hit and some miss
+fn rays(rng: &mut Rng) -> Vec<Ray> {
+    let start = vec(0.0, 0.0, -120.0);
+    return (0..RAYS)
+        .map(|_| Ray {
+            start_pos: start,
+            direction_vector: norm(point(rng) - start),
+            time: 0.0,
+        })
+        .collect();
+}
+
+/// Rays from points spread through the scene towards a light above it, and how far away the light is
+fn shadow_rays(rng: &mut Rng) -> Vec<(Ray, f32)> {
+    let light = vec(0.0, 200.0, 0.0);
+    return (0..RAYS)
+        .map(|_| {
+            let start = point(rng);
+            let ray = Ray {
+                start_pos: start,
+                direction_vector: norm(light - start),
+                time: 0.0,
+            };
+            (ray, mag(&(light - start)))
+        })
+        .collect();
+}
+
+/// A scene of spheres and triangles, with `accel` built over it the same way a loaded scene is
+fn world(sphere_count: usize, triangle_count: usize, accel: Accel) -> World {
+    let mut rng = Rng::new(1);
+    let mut world = World::default();
+    for sphere in spheres(&mut rng, sphere_count) {
+        world.add(sphere);
+    }
+    for triangle in triangles(&mut rng, triangle_count) {
+        world.add(triangle);
+    }
+    build_accel(&mut world, accel);
+    return world;
+}
+
+/// What `triangle_hit` is given when nothing has been hit yet
+fn nothing(r: Ray) -> RayHit {
+    return RayHit {
+        t: f32::MAX,
+        mat: GRAY,
+        intersect: r.start_pos,
+        surface_normal: r.direction_vector,
+        id: -1,
+        u: 0.0,
+        v: 0.0,
+        tangents: None,
+    };
+}
+
+fn bench_sphere_intersect(c: &mut Criterion) {
+    let mut rng = Rng::new(2);
+    let spheres = spheres(&mut rng, SPHERES);
+    let rays = rays(&mut rng);
+    c.bench_function("sphere_intersect", |b| {
+        b.iter(|| {
+            let mut hits = 0;
+            for r in &rays {
+                for s in &spheres {
+                    if sphere_intersect(black_box(s), black_box(r)) > 0.0 {
+                        hits += 1;
+                    }
+                }
+            }
+            return hits;
+        })
+    });
+}
+
+fn bench_triangle_hit(c: &mut Criterion) {
+    let mut rng = Rng::new(3);
+    let triangles = triangles(&mut rng, TRIANGLES);
+    let rays = rays(&mut rng);
+    c.bench_function("triangle_hit", |b| {
+        b.iter(|| {
+            let mut hits = 0;
+            for &r in &rays {
+                for &t in &triangles {
+                    if triangle_hit(black_box(t), black_box(r), nothing(r)).t > 0.0 {
+                        hits += 1;
+                    }
+                }
+            }
+            return hits;
+        })
+    });
+}
+
+fn bench_find_closest_hit(c: &mut Criterion) {
+    let rays = rays(&mut Rng::new(4));
+    let mut group = c.benchmark_group("find_closest_hit");
+    for (name, accel) in [
+        ("bvh", Accel::Bvh),
+        ("grid", Accel::Grid),
+        ("none", Accel::None),
+    ] {
+        let world = world(SPHERES, TRIANGLES, accel);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &world, |b, world| {
+            b.iter(|| {
+                for &r in &rays {
+                    black_box(find_closest_hit(black_box(r), -1, world));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Closest hits in a scene of nothing but triangles, where how quick `triangle_hit` is decides everything
+fn bench_triangle_scene(c: &mut Criterion) {
+    let rays = rays(&mut Rng::new(5));
+    let mut group = c.benchmark_group("find_closest_hit_triangles");
+    for (name, accel) in [("bvh", Accel::Bvh), ("none", Accel::None)] {
+        let world = world(0, MESH_TRIANGLES, accel);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &world, |b, world| {
+            b.iter(|| {
+                for &r in &rays {
+                    black_box(find_closest_hit(black_box(r), -1, world));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Closest hits among ten thousand spheres with no BVH or grid, testing the spheres' flat arrays in one pass next to
+/// testing each sphere in turn through the objects list
+fn bench_sphere_arrays(c: &mut Criterion) {
+    let rays = rays(&mut Rng::new(8));
+    let mut group = c.benchmark_group("find_closest_hit_10k_spheres");
+    let arrays = world(MANY_SPHERES, 0, Accel::None);
+    let mut objects = world(MANY_SPHERES, 0, Accel::None);
+    objects.spheres = None;
+    for (name, world) in [("arrays", &arrays), ("objects", &objects)] {
+        group.bench_with_input(BenchmarkId::from_parameter(name), world, |b, world| {
+            b.iter(|| {
+                for &r in &rays {
+                    black_box(find_closest_hit(black_box(r), -1, world));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Shadow rays answered by `is_occluded`, which stops at the first thing in the way, next to the same rays answered
+/// by finding the closest hit and checking it's before the light
+fn bench_shadows(c: &mut Criterion) {
+    let rays = shadow_rays(&mut Rng::new(6));
+    let mut group = c.benchmark_group("shadows");
+    for (name, accel) in [
+        ("bvh", Accel::Bvh),
+        ("grid", Accel::Grid),
+        ("none", Accel::None),
+    ] {
+        let world = world(SPHERES, TRIANGLES, accel);
+        group.bench_with_input(BenchmarkId::new("is_occluded", name), &world, |b, world| {
+            b.iter(|| {
+                for &(r, dist) in &rays {
+                    black_box(is_occluded(black_box(r), dist, -1, world));
+                }
+            })
+        });
+        group.bench_with_input(
+            BenchmarkId::new("find_closest_hit", name),
+            &world,
+            |b, world| {
+                b.iter(|| {
+                    for &(r, dist) in &rays {
+                        let hit = find_closest_hit(black_box(r), -1, world);
+                        black_box(hit.t > 0.0 && hit.t < dist);
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// The vector operations the simd feature swaps out, on their own, so changes to them aren't lost among the rest
+/// of a hit test
+fn bench_vec_math(c: &mut Criterion) {
+    let mut rng = Rng::new(7);
+    let points: Vec<Vec3> = (0..RAYS).map(|_| point(&mut rng)).collect();
+    let mut group = c.benchmark_group("vec_math");
+    group.bench_function("dot", |b| {
+        b.iter(|| {
+            let mut total = 0.0;
+            for pair in black_box(&points).windows(2) {
+                total += pair[0] * pair[1];
+            }
+            return total;
+        })
+    });
+    group.bench_function("cross", |b| {
+        b.iter(|| {
+            for pair in black_box(&points).windows(2) {
+                black_box(cross(pair[0], pair[1]));
+            }
+        })
+    });
+    group.bench_function("norm", |b| {
+        b.iter(|| {
+            for &p in black_box(&points) {
+                black_box(norm(p));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_sphere_intersect,
+    bench_triangle_hit,
+    bench_find_closest_hit,
+    bench_triangle_scene,
+    bench_sphere_arrays,
+    bench_shadows,
+    bench_vec_math
+);
+criterion_main!(benches);
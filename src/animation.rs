@@ -0,0 +1,52 @@
+// Rendering a scene as a numbered run of frames, with anything animated moved along a little further for each one.
+
+use std::path::Path;
+
+/// How many frames to render and what to number their files
+#[derive(Debug, Copy, Clone)]
+pub struct Frames {
+    pub count: u32,
+    /// Number the first frame's file gets
+    pub start: u32,
+    /// Digits frame numbers are padded out to with zeros
+    pub padding: usize,
+}
+
+impl Default for Frames {
+    fn default() -> Self {
+        return Frames {
+            count: 1,
+            start: 1,
+            padding: 4,
+        };
+    }
+}
+
+impl Frames {
+    /// How far through the animation a frame is, from 0 at the first frame to 1 at the last
+    pub fn time(&self, frame: u32) -> f32 {
+        if self.count <= 1 {
+            return 0.0;
+        }
+        return frame as f32 / (self.count - 1) as f32;
+    }
+
+    /// Where a frame of an output goes, with its number put before the extension, like out_0001.png. A single frame
+    /// keeps the path as it was given
+    /// # Arguements
+    /// * 'path' - the output without a frame number
+    /// * 'frame' - which frame, counting from 0
+    pub fn path(&self, path: &str, frame: u32) -> String {
+        if self.count <= 1 {
+            return path.to_string();
+        }
+        let path = Path::new(path);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let number = format!("{:0width$}", self.start + frame, width = self.padding);
+        let name = match path.extension() {
+            Some(extension) => format!("{}_{}.{}", stem, number, extension.to_string_lossy()),
+            None => format!("{}_{}", stem, number),
+        };
+        return path.with_file_name(name).to_string_lossy().into_owned();
+    }
+}
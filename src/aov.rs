@@ -0,0 +1,131 @@
+// Extra images written alongside the render, showing what the camera sees first through each pixel rather than its
+// color. Handy for compositing and for seeing what went wrong in a scene.
+
+use crate::denoise::Surface;
+
+/// How distances are spread between black and white in a depth image
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DepthScale {
+    /// Evenly between the near and far distances
+    #[default]
+    Linear,
+    /// Gives more of the grays to things up close, so nearby detail isn't lost in a deep scene
+    Log,
+}
+
+/// Which extra images to write, and how
+#[derive(Debug, Clone, Default)]
+pub struct Aovs {
+    /// Where to write how far away the first thing hit is
+    pub depth: Option<String>,
+    /// The distances drawn as black and white. The nearest and furthest hits are used when not given
+    pub depth_range: Option<(f32, f32)>,
+    pub depth_scale: DepthScale,
+    /// Where to write which way the first thing hit faces
+    pub normal: Option<String>,
+    /// Where to write which object is hit first, as a color for each
+    pub id: Option<String>,
+}
+
+impl Aovs {
+    /// Whether any extra images are wanted at all
+    pub fn any(&self) -> bool {
+        return self.depth.is_some() || self.normal.is_some() || self.id.is_some();
+    }
+}
+
+/// Draws how far away the first hit through each pixel is, from black up close to white far away. Pixels that don't
+/// hit anything are white.
+/// # Arguements
+/// * 'surfaces' - what each pixel sees first, row by row
+/// * 'range' - the distances drawn as black and white, or None to use the nearest and furthest hits
+/// * 'scale' - how distances in between are spread
+pub fn depth_image(
+    surfaces: &[Option<Surface>],
+    width: u32,
+    height: u32,
+    range: Option<(f32, f32)>,
+    scale: DepthScale,
+) -> image::GrayImage {
+    let depths = surfaces.iter().flatten().map(|s| s.depth);
+    let (near, far) = range.unwrap_or_else(|| {
+        depths.fold((f32::MAX, 0.0), |(near, far), d| (near.min(d), far.max(d)))
+    });
+    let map = |d: f32| match scale {
+        DepthScale::Linear => d,
+        // shifted so the near distance maps to 0, which keeps it finite however close that is
+        DepthScale::Log => (d - near).max(0.0).ln_1p(),
+    };
+    let (low, high) = (map(near), map(far));
+    let mut img = image::GrayImage::new(width, height);
+    for (pixel, surface) in img.pixels_mut().zip(surfaces) {
+        let shade = match surface {
+            Some(s) if high > low => ((map(s.depth) - low) / (high - low)).clamp(0.0, 1.0),
+            Some(_) => 0.0,
+            None => 1.0,
+        };
+        *pixel = image::Luma([(shade * 255.0).round() as u8]);
+    }
+    return img;
+}
+
+/// Draws which way the first hit through each pixel faces, with x, y, and z going from -1 to 1 drawn as red, green,
+/// and blue from 0 to full. Pixels that don't hit anything are mid gray.
+/// # Arguements
+/// * 'surfaces' - what each pixel sees first, row by row
+pub fn normal_image(surfaces: &[Option<Surface>], width: u32, height: u32) -> image::RgbImage {
+    let mut img = image::RgbImage::new(width, height);
+    for (pixel, surface) in img.pixels_mut().zip(surfaces) {
+        let encode = |c: f32| ((c * 0.5 + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8;
+        *pixel = match surface {
+            Some(s) => image::Rgb([encode(s.normal.x), encode(s.normal.y), encode(s.normal.z)]),
+            None => image::Rgb([128, 128, 128]),
+        };
+    }
+    return img;
+}
+
+/// Draws which object the first hit through each pixel is on, each in its own bright color. Pixels that don't hit
+/// anything are black.
+/// # Arguements
+/// * 'surfaces' - what each pixel sees first, row by row
+pub fn id_image(surfaces: &[Option<Surface>], width: u32, height: u32) -> image::RgbImage {
+    let mut img = image::RgbImage::new(width, height);
+    for (pixel, surface) in img.pixels_mut().zip(surfaces) {
+        *pixel = match surface {
+            Some(s) => id_color(s.id),
+            None => image::Rgb([0, 0, 0]),
+        };
+    }
+    return img;
+}
+
+/// A fully bright color for an object id. The id is scrambled into a hue, so ids next to each other come out far apart
+/// on the color wheel rather than in near matching shades.
+fn id_color(id: i8) -> image::Rgb<u8> {
+    let hash = (id as u8 as u32).wrapping_mul(0x9e37_79b9);
+    let hue = (hash >> 8) as f32 / 16_777_216.0 * 6.0;
+    // how far up or down the one channel that's changing is, between the six corners of the color wheel
+    let rising = (hue.fract() * 255.0).round() as u8;
+    let falling = 255 - rising;
+    return image::Rgb(match hue as u32 {
+        0 => [255, rising, 0],
+        1 => [falling, 255, 0],
+        2 => [0, 255, rising],
+        3 => [0, falling, 255],
+        4 => [rising, 0, 255],
+        _ => [255, 0, falling],
+    });
+}
+
+/// Turns how much of each pixel is covered into an alpha channel, from 0 where only the background shows to 255 where
+/// something fills the whole pixel
+/// # Arguements
+/// * 'coverage' - each pixel's coverage from 0 to 1, row by row
+pub fn alpha_image(coverage: &[f32], width: u32, height: u32) -> image::GrayImage {
+    let mut img = image::GrayImage::new(width, height);
+    for (pixel, &covered) in img.pixels_mut().zip(coverage) {
+        *pixel = image::Luma([(covered.clamp(0.0, 1.0) * 255.0).round() as u8]);
+    }
+    return img;
+}
@@ -0,0 +1,296 @@
+// Bounding volume hierarchy over everything in a World, so a ray only tests the objects whose boxes it passes through.
+// Built once after the scene is parsed and stored on the World.
+
+use crate::geometry::{aabb_union, ray_aabb_intersect, Aabb, RayHit, World};
+use crate::stats::Counter;
+use crate::vec_math::{Ray, Vec3};
+
+/// Most objects a leaf holds before it gets split
+const LEAF_SIZE: usize = 4;
+
+#[derive(Debug, Copy, Clone)]
+enum Node {
+    /// `count` entries of `Bvh::order` starting at `start`
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        count: usize,
+    },
+    /// Children are split along `axis`, with `left` holding the lower half
+    Split {
+        bounds: Aabb,
+        left: usize,
+        right: usize,
+        axis: usize,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    /// Indices into the world's objects, grouped so each leaf owns a contiguous run
+    order: Vec<usize>,
+    nodes: Vec<Node>,
+    /// Things with no finite bounds (planes), tested against every ray
+    unbounded: Vec<usize>,
+}
+
+fn center(b: &Aabb) -> Vec3 {
+    return (b.min + b.max) * 0.5;
+}
+
+pub(crate) fn axis_value(v: Vec3, axis: usize) -> f32 {
+    match axis {
+        0 => return v.x,
+        1 => return v.y,
+        _ => return v.z,
+    }
+}
+
+/// Builds the hierarchy for everything currently in the world. Rebuild it if the world changes.
+///
+/// # Arguements
+/// * 'world' - the parsed scene, with its bounds already built
+pub fn build_bvh(world: &World) -> Bvh {
+    let mut bvh = Bvh::default();
+
+    let mut bounded: Vec<(usize, Aabb)> = Vec::new();
+    for (i, bounds) in world.bounds.iter().enumerate() {
+        match bounds {
+            Some(b) => bounded.push((i, *b)),
+            None => bvh.unbounded.push(i),
+        }
+    }
+
+    if !bounded.is_empty() {
+        build_node(&mut bvh, &mut bounded);
+    }
+    return bvh;
+}
+
+/// Adds a node covering `items` (and everything under it), returning its index
+fn build_node(bvh: &mut Bvh, items: &mut [(usize, Aabb)]) -> usize {
+    let bounds = items.iter().map(|(_, b)| *b).reduce(aabb_union).unwrap();
+    let index = bvh.nodes.len();
+
+    if items.len() <= LEAF_SIZE {
+        bvh.nodes.push(Node::Leaf {
+            bounds,
+            start: bvh.order.len(),
+            count: items.len(),
+        });
+        bvh.order.extend(items.iter().map(|(i, _)| *i));
+        return index;
+    }
+
+    // split at the median centroid along whichever axis the centroids spread out the most over
+    let centroids = items
+        .iter()
+        .map(|(_, b)| Aabb {
+            min: center(b),
+            max: center(b),
+        })
+        .reduce(aabb_union)
+        .unwrap();
+    let extent = centroids.max - centroids.min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+
+    items.sort_by(|a, b| axis_value(center(&a.1), axis).total_cmp(&axis_value(center(&b.1), axis)));
+    let mid = items.len() / 2;
+
+    // placeholder until the children exist
+    bvh.nodes.push(Node::Leaf {
+        bounds,
+        start: 0,
+        count: 0,
+    });
+    let (low, high) = items.split_at_mut(mid);
+    let left = build_node(bvh, low);
+    let right = build_node(bvh, high);
+    bvh.nodes[index] = Node::Split {
+        bounds,
+        left,
+        right,
+        axis,
+    };
+    return index;
+}
+
+/// Tests one object and keeps it in `best` if it beats what's there. Ties go to whichever comes first in the
+/// world, so the result doesn't depend on the order objects are tested in.
+pub(crate) fn consider(
+    world: &World,
+    index: usize,
+    ray: Ray,
+    id: i8,
+    best: &mut Option<(RayHit, usize)>,
+) {
+    let object = &world.objects[index];
+    if object.id() == id {
+        return;
+    }
+    world.stats.count(Counter::IntersectionTest);
+    // an exact tie has to be seen to be settled, so nothing is ruled out by distance here
+    let Some(temp) = object.hit(&ray, f32::MAX) else {
+        return;
+    };
+    let better = match best {
+        None => true,
+        Some((hit, order)) => temp.t < hit.t || (temp.t == hit.t && index < *order),
+    };
+    if better {
+        *best = Some((temp, index));
+    }
+}
+
+/// Same answer as testing every object in the world in order: the closest hit, and on an exact tie, whichever
+/// object comes first. None if nothing was hit.
+///
+/// # Arguements
+/// * 'bvh' - hierarchy built from `world`
+/// * 'ray' - ray to trace
+/// * 'id' - id of an object to ignore, usually the one the ray is leaving
+/// * 'world' - the objects themselves
+pub fn bvh_closest_hit(bvh: &Bvh, ray: Ray, id: i8, world: &World) -> Option<RayHit> {
+    let mut best: Option<(RayHit, usize)> = None;
+
+    for &i in &bvh.unbounded {
+        consider(world, i, ray, id, &mut best);
+    }
+
+    if bvh.nodes.is_empty() {
+        return best.map(|(hit, _)| hit);
+    }
+
+    let mut stack = vec![0];
+    while let Some(node_index) = stack.pop() {
+        let t_max = match &best {
+            Some((hit, _)) => hit.t,
+            None => f32::MAX,
+        };
+
+        match bvh.nodes[node_index] {
+            Node::Leaf {
+                bounds,
+                start,
+                count,
+            } => {
+                if !ray_aabb_intersect(&bounds, &ray, t_max) {
+                    continue;
+                }
+                for &i in &bvh.order[start..start + count] {
+                    consider(world, i, ray, id, &mut best);
+                }
+            }
+            Node::Split {
+                bounds,
+                left,
+                right,
+                axis,
+            } => {
+                if !ray_aabb_intersect(&bounds, &ray, t_max) {
+                    continue;
+                }
+                // push the far child first so the near one gets looked at first
+                if axis_value(ray.direction_vector, axis) < 0.0 {
+                    stack.push(left);
+                    stack.push(right);
+                } else {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+    }
+
+    return best.map(|(hit, _)| hit);
+}
+
+/// Whether anything is hit closer than `t_max` that `stop` says counts, stopping at the first one rather than looking
+/// for the closest. Hits come in no particular order. Quicker than `bvh_closest_hit` for shadow rays, where what's in
+/// the way often doesn't matter.
+///
+/// # Arguements
+/// * 'bvh' - hierarchy built from `world`
+/// * 'ray' - ray to trace
+/// * 't_max' - how far along the ray to look
+/// * 'id' - id of an object to ignore, usually the one the ray is leaving
+/// * 'world' - the objects themselves
+/// * 'stop' - whether a hit counts, or should be looked past
+pub fn bvh_any_hit(
+    bvh: &Bvh,
+    ray: Ray,
+    t_max: f32,
+    id: i8,
+    world: &World,
+    stop: &mut dyn FnMut(&RayHit) -> bool,
+) -> bool {
+    for &i in &bvh.unbounded {
+        if blocks(world, i, ray, t_max, id, stop) {
+            return true;
+        }
+    }
+
+    if bvh.nodes.is_empty() {
+        return false;
+    }
+
+    let mut stack = vec![0];
+    while let Some(node_index) = stack.pop() {
+        match bvh.nodes[node_index] {
+            Node::Leaf {
+                bounds,
+                start,
+                count,
+            } => {
+                if !ray_aabb_intersect(&bounds, &ray, t_max) {
+                    continue;
+                }
+                for &i in &bvh.order[start..start + count] {
+                    if blocks(world, i, ray, t_max, id, stop) {
+                        return true;
+                    }
+                }
+            }
+            Node::Split {
+                bounds,
+                left,
+                right,
+                ..
+            } => {
+                if !ray_aabb_intersect(&bounds, &ray, t_max) {
+                    continue;
+                }
+                stack.push(right);
+                stack.push(left);
+            }
+        }
+    }
+
+    return false;
+}
+
+/// Whether one object is hit closer than `t_max` and `stop` says the hit counts, skipping the one with `id`
+pub(crate) fn blocks(
+    world: &World,
+    index: usize,
+    ray: Ray,
+    t_max: f32,
+    id: i8,
+    stop: &mut dyn FnMut(&RayHit) -> bool,
+) -> bool {
+    let object = &world.objects[index];
+    if object.id() == id {
+        return false;
+    }
+    world.stats.count(Counter::IntersectionTest);
+    return match object.hit(&ray, t_max) {
+        Some(hit) => stop(&hit),
+        None => false,
+    };
+}
@@ -0,0 +1,77 @@
+// Smooths out the noise left by too few samples, without blurring across the edges of things. Neighbouring pixels are
+// only blended when the surfaces seen through them face the same way at about the same distance.
+
+use crate::vec_math::{vec, Vec3};
+
+/// Pixels out from the middle of the filter, each way
+const RADIUS: i32 = 2;
+
+/// How quickly pixels further away count for less, in pixels
+const SPATIAL_SIGMA: f32 = 1.5;
+
+/// How sharply a difference in facing cuts a neighbour off. Higher keeps creases crisper
+const NORMAL_POWER: i32 = 32;
+
+/// How different a neighbour's distance can be before it stops counting, as a fraction of the pixel's own distance
+const DEPTH_SIGMA: f32 = 0.1;
+
+/// What the camera sees first through the middle of a pixel
+#[derive(Debug, Copy, Clone)]
+pub struct Surface {
+    /// Which way the surface faces
+    pub normal: Vec3,
+    /// How far along the camera ray it was
+    pub depth: f32,
+    /// Which object it's on
+    pub id: i8,
+}
+
+/// Blends each pixel with the neighbours that are showing the same surface as it. Pixels showing the background only
+/// blend with other background pixels.
+/// # Arguements
+/// * 'colors' - the image in linear light, row by row
+/// * 'surfaces' - what each pixel sees first, or None for the background
+/// * 'width' - the image's width in pixels
+/// * 'height' - the image's height in pixels
+pub fn denoise(
+    colors: &[Vec3],
+    surfaces: &[Option<Surface>],
+    width: u32,
+    height: u32,
+) -> Vec<Vec3> {
+    let (width, height) = (width as i32, height as i32);
+    let mut out = Vec::with_capacity(colors.len());
+    for y in 0..height {
+        for x in 0..width {
+            let here = surfaces[(y * width + x) as usize];
+            let mut sum = vec(0.0, 0.0, 0.0);
+            let mut total = 0.0;
+            for ny in (y - RADIUS).max(0)..=(y + RADIUS).min(height - 1) {
+                for nx in (x - RADIUS).max(0)..=(x + RADIUS).min(width - 1) {
+                    let i = (ny * width + nx) as usize;
+                    let distance2 = ((nx - x) * (nx - x) + (ny - y) * (ny - y)) as f32;
+                    let spatial = (-distance2 / (2.0 * SPATIAL_SIGMA * SPATIAL_SIGMA)).exp();
+                    let weight = spatial * similarity(here, surfaces[i]);
+                    sum = sum + colors[i] * weight;
+                    total += weight;
+                }
+            }
+            // the pixel always matches itself, so total is never 0
+            out.push(sum * (1.0 / total));
+        }
+    }
+    return out;
+}
+
+/// How much alike two pixels' surfaces are, from 0 for nothing alike to 1 for the same
+fn similarity(a: Option<Surface>, b: Option<Surface>) -> f32 {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let facing = (a.normal * b.normal).max(0.0).powi(NORMAL_POWER);
+            let gap = (a.depth - b.depth) / (DEPTH_SIGMA * a.depth);
+            return facing * (-gap * gap).exp();
+        }
+        (None, None) => return 1.0,
+        _ => return 0.0,
+    }
+}
@@ -1,42 +1,541 @@
-use crate::vec_math::{cross, norm, Ray, Vec3};
+use crate::bvh::Bvh;
+use crate::grid::Grid;
+use crate::noise::{marble, wood};
+use crate::solver::solve_quartic;
+use crate::stats::{Counter, RenderStats};
+use crate::vec_math::{cross, mag, norm, vec, Mat4, Ray, Vec3};
+use image::{Rgb, Rgb32FImage};
+use std::sync::Arc;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// What kind of surface a material is, with its color and whatever else only that kind needs
+#[derive(Debug, Copy, Clone)]
 pub enum MaterialType {
-    Reflective,
-    Glossy,
-    Matte,
+    /// A mirror
+    Reflective {
+        /// Colors everything it reflects, and its own shading where it doesn't reflect all of it
+        tint: Albedo,
+        /// How much of the color comes from what it reflects, from 0 to 1. The rest is its own shading
+        reflectivity: f32,
+        /// How rough it is. Reflections are jittered by up to this much, 0 for a clean mirror
+        fuzz: f32,
+    },
+    /// Matte with a highlight from the light
+    Glossy {
+        albedo: Albedo,
+        /// Specular exponent. Higher is a tighter highlight
+        shininess: f32,
+        /// Highlight model, or None to use whatever the render asks for
+        specular: Option<SpecularModel>,
+        /// Tints the highlights, and the reflection
+        specular_color: Vec3,
+        /// How much of what's around it the surface reflects, from 0 for none to 1 for all
+        reflectivity: f32,
+        /// How blurred the reflection is, from 0 for a sharp mirror to 1 for rays scattered up to right angles
+        /// from it
+        roughness: f32,
+    },
+    /// Lit by the lights and the ambient light, with no highlight
+    Matte { albedo: Albedo },
+    /// Clear, bending rays that pass through
+    Refractive {
+        /// Colors the light that gets through it onto whatever it shadows
+        tint: Albedo,
+        /// Index of refraction
+        ior: f32,
+        /// How much of each channel is soaked up per unit of distance inside
+        absorption: Vec3,
+    },
+    /// Glows at `intensity` times its color no matter how it's lit. Doesn't light anything else, and lets light
+    /// through to whatever is behind it so it never casts a shadow.
+    Emissive { color: Albedo, intensity: f32 },
+}
+
+/// How glossy highlights are worked out
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SpecularModel {
+    /// Compares the light's mirror direction with the view direction
+    #[default]
+    Phong,
+    /// Compares the normal with the half-way vector between the light and the viewer. Highlights are wider
+    /// for the same shininess, and stay round at grazing angles
+    Blinn,
+}
+
+/// Two colors alternating in cubes of `tile` units, fixed in world space. Whatever surface passes through them
+/// picks up the pattern, so a flat floor gets squares and a sphere gets patches.
+#[derive(Debug, Copy, Clone)]
+pub struct Checker {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub tile: f32,
+    /// A corner of one of the `a` cubes
+    pub origin: Vec3,
 }
 
+/// Noisy bumps faked by tilting a surface's normal, without moving the surface itself
+#[derive(Debug, Copy, Clone)]
+pub struct Bump {
+    /// How tall the bumps are
+    pub scale: f32,
+    /// About how many bumps there are per unit
+    pub frequency: f32,
+}
+
+/// Two colors blended by a noise pattern, see `noise::marble` and `noise::wood`
+#[derive(Debug, Copy, Clone)]
+pub struct NoiseBands {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub frequency: f32,
+}
+
+/// Where a material's diffuse color comes from
+#[derive(Debug, Copy, Clone)]
+pub enum Albedo {
+    /// The same color everywhere
+    Solid(Vec3),
+    Checker(Checker),
+    /// An image wrapped around the object by the hit's texture coordinates. Indexes the scene's textures.
+    Texture(usize),
+    Marble(NoiseBands),
+    Wood(NoiseBands),
+}
+
+/// A kind of surface, along with the modifiers any kind can have
 #[derive(Debug, Copy, Clone)]
 pub struct Material {
-    pub(crate) color: Vec3,
     pub(crate) t: MaterialType,
+    /// Tangent space normal map, indexing the scene's textures like `Albedo::Texture`
+    pub(crate) normal_map: Option<usize>,
+    pub(crate) bump: Option<Bump>,
+    /// How much light still reaches this material through whatever shadows it, or None to use the scene's
+    pub(crate) shadow: Option<f32>,
+}
+
+impl Material {
+    /// A material with nothing but its kind, and no modifiers
+    pub const fn new(t: MaterialType) -> Material {
+        return Material {
+            t,
+            normal_map: None,
+            bump: None,
+            shadow: None,
+        };
+    }
+
+    /// Where this material's color comes from, whatever kind it is
+    pub fn color(&self) -> Albedo {
+        match self.t {
+            MaterialType::Matte { albedo } | MaterialType::Glossy { albedo, .. } => return albedo,
+            MaterialType::Reflective { tint, .. } | MaterialType::Refractive { tint, .. } => {
+                return tint
+            }
+            MaterialType::Emissive { color, .. } => return color,
+        }
+    }
+
+    /// The color of this material at a point on its surface
+    /// # Arguements
+    /// * 'p' - the point, used by patterns fixed in world space
+    /// * 'u' - horizontal texture coordinate of the point, from 0 to 1
+    /// * 'v' - vertical texture coordinate of the point, from 0 at the top of the image to 1 at the bottom
+    /// * 'textures' - the images texture materials refer to
+    pub fn albedo(&self, p: Vec3, u: f32, v: f32, textures: &[Rgb32FImage]) -> Vec3 {
+        match self.color() {
+            Albedo::Solid(color) => return color,
+            Albedo::Texture(index) => {
+                let Some(image) = textures.get(index) else {
+                    return vec(0.0, 0.0, 0.0);
+                };
+                let [r, g, b] = nearest_pixel(image, u, v).0;
+                return vec(r, g, b);
+            }
+            Albedo::Marble(bands) => {
+                let t = marble(p, bands.frequency);
+                return bands.a * (1.0 - t) + bands.b * t;
+            }
+            Albedo::Wood(bands) => {
+                let t = wood(p, bands.frequency);
+                return bands.a * (1.0 - t) + bands.b * t;
+            }
+            Albedo::Checker(c) => {
+                let local = p - c.origin;
+                let parity = (f32::floor(local.x / c.tile)
+                    + f32::floor(local.y / c.tile)
+                    + f32::floor(local.z / c.tile)) as i64;
+                if parity.rem_euclid(2) == 0 {
+                    return c.a;
+                }
+                return c.b;
+            }
+        }
+    }
 }
 
+/// The pixel of an image nearest to some texture coordinates
+pub fn nearest_pixel(image: &Rgb32FImage, u: f32, v: f32) -> Rgb<f32> {
+    // u and v of exactly 1 (the seam and the bottom pole) would be one past the edge
+    let x = ((u.clamp(0.0, 1.0) * image.width() as f32) as u32).min(image.width() - 1);
+    let y = ((v.clamp(0.0, 1.0) * image.height() as f32) as u32).min(image.height() - 1);
+    return *image.get_pixel(x, y);
+}
+
+/// Absorption of something perfectly clear
+pub const CLEAR: Vec3 = Vec3 {
+    x: 0.0,
+    y: 0.0,
+    z: 0.0,
+};
+
+/// Full brightness in every channel
+pub const WHITE: Vec3 = Vec3 {
+    x: 1.0,
+    y: 1.0,
+    z: 1.0,
+};
+
+/// Specular exponent used when a glossy material doesn't give one
+pub const DEFAULT_SHININESS: f32 = 11.0;
+
+/// Placeholder material for shapes that are only used for their geometry
+const NO_MAT: Material = Material::new(MaterialType::Matte {
+    albedo: Albedo::Solid(Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    }),
+});
+
+/// A sphere. An `inverted` sphere has its normals pointing inward, for domes and bubbles seen from inside.
 #[derive(Debug, Copy, Clone)]
 pub struct Sphere {
+    /// Where the middle is as the shutter opens
     pub center: Vec3,
+    /// How far the middle moves by the time the shutter closes
+    pub velocity: Vec3,
     pub radius: f32,
+    pub inverted: bool,
     pub mat: Material,
     pub id: i8,
 }
 
+/// A triangle. With `vertex_normals` set, the normals at a, b, and c are blended across the face for smooth shading,
+/// otherwise the flat normal from the winding is used. `uvs` are the texture coordinates at a, b, and c, blended the
+/// same way.
+/// A `double_sided` triangle turns its normal toward whichever side it's seen from, otherwise `cull_backfaces`
+/// decides whether hits on its back count at all.
 #[derive(Debug, Copy, Clone)]
 pub struct Triangle {
     pub a: Vec3,
     pub b: Vec3,
     pub c: Vec3,
+    pub vertex_normals: Option<[Vec3; 3]>,
+    pub uvs: Option<[(f32, f32); 3]>,
+    pub double_sided: bool,
+    pub cull_backfaces: bool,
+    pub mat: Material,
+    pub id: i8,
+}
+
+/// A placed triangle with what its hit test needs worked out once up front, since it never moves again
+#[derive(Debug, Copy, Clone)]
+pub struct PreparedTriangle {
+    pub triangle: Triangle,
+    /// `a - b` and `a - c`
+    edges: (Vec3, Vec3),
+    /// Normalized normal from the winding
+    flat_normal: Vec3,
+}
+
+impl PreparedTriangle {
+    pub fn new(triangle: Triangle) -> PreparedTriangle {
+        return PreparedTriangle {
+            triangle,
+            edges: (triangle.a - triangle.b, triangle.a - triangle.c),
+            flat_normal: norm(cross(triangle.b - triangle.a, triangle.c - triangle.a)),
+        };
+    }
+}
+
+/// An infinite plane passing through `point`. The normal should be normalized.
+#[derive(Debug, Copy, Clone)]
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub mat: Material,
+    pub id: i8,
+}
+
+/// A finite cylinder with flat end caps. It extends `height` units from `base` along `axis`, which should be normalized.
+#[derive(Debug, Copy, Clone)]
+pub struct Cylinder {
+    pub base: Vec3,
+    pub axis: Vec3,
+    pub radius: f32,
+    pub height: f32,
+    pub mat: Material,
+    pub id: i8,
+}
+
+/// A finite cone opening from `apex` along `axis` (normalized) until it reaches `radius` at `height` units away.
+/// `capped` closes off the wide end with a disk.
+#[derive(Debug, Copy, Clone)]
+pub struct Cone {
+    pub apex: Vec3,
+    pub axis: Vec3,
+    pub radius: f32,
+    pub height: f32,
+    pub capped: bool,
+    pub mat: Material,
+    pub id: i8,
+}
+
+/// A flat, two-sided circle. Rays hitting it from behind see a flipped normal.
+#[derive(Debug, Copy, Clone)]
+pub struct Disk {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f32,
     pub mat: Material,
     pub id: i8,
 }
 
-pub fn triangle_hit(tr: Triangle, r: Ray, close: RayHit) -> RayHit {
-    let a = tr.a.x - tr.b.x;
-    let b = tr.a.y - tr.b.y;
-    let c = tr.a.z - tr.b.z;
-    let d = tr.a.x - tr.c.x;
-    let e = tr.a.y - tr.c.y;
-    let f = tr.a.z - tr.c.z;
+/// A ring around `center` in the plane perpendicular to `axis` (normalized).
+/// `major_radius` is the distance to the middle of the tube and `minor_radius` is the tube's thickness.
+#[derive(Debug, Copy, Clone)]
+pub struct Torus {
+    pub center: Vec3,
+    pub axis: Vec3,
+    pub major_radius: f32,
+    pub minor_radius: f32,
+    pub mat: Material,
+    pub id: i8,
+}
+
+/// A sphere stretched along each axis by the matching component of `radii`
+#[derive(Debug, Copy, Clone)]
+pub struct Ellipsoid {
+    pub center: Vec3,
+    pub radii: Vec3,
+    pub mat: Material,
+    pub id: i8,
+}
+
+/// A group of triangles sharing one material and one id, so the whole group acts like a single object
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    pub triangles: Vec<PreparedTriangle>,
+    pub double_sided: bool,
+    pub mat: Material,
+    pub id: i8,
+}
+
+/// A closed convex shape that can be used as one side of a CSG operation
+#[derive(Debug, Copy, Clone)]
+pub enum Solid {
+    Sphere { center: Vec3, radius: f32 },
+    Cuboid { min: Vec3, max: Vec3 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+/// Two solids combined with a boolean operation. For a difference the right solid is cut out of the left.
+#[derive(Debug, Copy, Clone)]
+pub struct Csg {
+    pub op: CsgOp,
+    pub left: Solid,
+    pub right: Solid,
+    pub mat: Material,
+    pub id: i8,
+}
+
+/// The stretch of a ray that lies inside a solid, along with the surface normals where it enters and leaves
+#[derive(Debug, Copy, Clone)]
+pub struct Span {
+    pub t_in: f32,
+    pub normal_in: Vec3,
+    pub t_out: f32,
+    pub normal_out: Vec3,
+}
+
+/// A placed copy of a shared group of triangles. Rays are moved into the group's own space to be tested,
+/// so any number of instances can share one set of triangles.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub triangles: Arc<Vec<PreparedTriangle>>,
+    pub to_world: Mat4,
+    pub to_object: Mat4,
+    pub id: i8,
+}
+
+/// An axis aligned bounding box
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+/// Anything a ray can hit. Shared by every render thread, so it can't hold anything they'd need to take turns with
+pub trait Hittable: std::fmt::Debug + Send + Sync {
+    /// The hit along `ray` closer than `t_max`, if there is one in front of the ray
+    fn hit(&self, ray: &Ray, t_max: f32) -> Option<RayHit>;
+    fn id(&self) -> i8;
+    /// Box around the whole object, or None if there isn't a finite one (planes)
+    fn bounds(&self) -> Option<Aabb>;
+    /// The object as a sphere, if it is one, so spheres can be gathered up and tested in bulk
+    fn as_sphere(&self) -> Option<&Sphere> {
+        return None;
+    }
+}
+
+/// Every object in a scene
+#[derive(Debug, Default)]
+pub struct World {
+    pub objects: Vec<Box<dyn Hittable>>,
+    /// Bounds of each entry in `objects`, filled in by `build_bounds` once parsing is done
+    pub bounds: Vec<Option<Aabb>>,
+    /// Built from the objects once parsing is done. Without it (or the grid) every ray tests every object.
+    pub bvh: Option<Bvh>,
+    /// Alternative to the BVH, picked with --accel=grid
+    pub grid: Option<Grid>,
+    /// The spheres pulled out into flat arrays, built when there's no BVH or grid and every ray tests everything
+    pub spheres: Option<SphereSoA>,
+    /// Counts of the rays traced through the world while rendering
+    pub stats: RenderStats,
+}
+
+/// The spheres in a World stored field by field. Testing a ray against all of them in a row then only pulls their
+/// centers and radii through the cache, not their materials.
+#[derive(Debug, Clone, Default)]
+pub struct SphereSoA {
+    cx: Vec<f32>,
+    cy: Vec<f32>,
+    cz: Vec<f32>,
+    vx: Vec<f32>,
+    vy: Vec<f32>,
+    vz: Vec<f32>,
+    radius: Vec<f32>,
+    ids: Vec<i8>,
+    /// Where each sphere is in the world's objects, for the full hit on whichever one wins
+    index: Vec<usize>,
+    /// Where everything that isn't a sphere is in the world's objects
+    others: Vec<usize>,
+}
+
+impl SphereSoA {
+    pub fn new(world: &World) -> SphereSoA {
+        let mut soa = SphereSoA::default();
+        for (i, object) in world.objects.iter().enumerate() {
+            let Some(s) = object.as_sphere() else {
+                soa.others.push(i);
+                continue;
+            };
+            soa.cx.push(s.center.x);
+            soa.cy.push(s.center.y);
+            soa.cz.push(s.center.z);
+            soa.vx.push(s.velocity.x);
+            soa.vy.push(s.velocity.y);
+            soa.vz.push(s.velocity.z);
+            soa.radius.push(s.radius);
+            soa.ids.push(s.id);
+            soa.index.push(i);
+        }
+        return soa;
+    }
+
+    /// Every sphere hit closer than `t_max`, as how far along the ray and where the sphere is in the world's objects,
+    /// in the same order as the objects
+    /// # Arguements
+    /// * 'ray' - ray to trace
+    /// * 'id' - id of spheres to ignore
+    /// * 't_max' - how far along the ray to look
+    /// * 'stats' - where each sphere tested is counted
+    pub fn hits<'a>(
+        &'a self,
+        ray: Ray,
+        id: i8,
+        t_max: f32,
+        stats: &'a RenderStats,
+    ) -> impl Iterator<Item = (f32, usize)> + 'a {
+        return (0..self.index.len()).filter_map(move |k| {
+            if self.ids[k] == id {
+                return None;
+            }
+            stats.count(Counter::IntersectionTest);
+            let t = self.intersect(k, &ray);
+            if t > 0.0 && t < t_max {
+                return Some((t, self.index[k]));
+            }
+            return None;
+        });
+    }
+
+    /// Where everything that isn't a sphere is in the world's objects
+    pub fn others(&self) -> &[usize] {
+        return &self.others;
+    }
+
+    /// Same sums as `sphere_intersect` on the sphere at `k`, moved to where it is at the ray's time, so a hit found
+    /// here is exactly the one `Sphere::hit` gives back
+    fn intersect(&self, k: usize, r: &Ray) -> f32 {
+        let d = r.direction_vector;
+        let ex = r.start_pos.x - (self.cx[k] + self.vx[k] * r.time);
+        let ey = r.start_pos.y - (self.cy[k] + self.vy[k] * r.time);
+        let ez = r.start_pos.z - (self.cz[k] + self.vz[k] * r.time);
+        let ddemc = d.x * ex + d.y * ey + d.z * ez;
+        let c = (ex * ex + ey * ey + ez * ez) - (self.radius[k] * self.radius[k]);
+
+        if ddemc > 0.0 && c > 0.0 {
+            return -1.0;
+        }
+
+        let ddd = d * d;
+        let discriminant = (ddemc * ddemc) - ddd * c;
+        if discriminant < 0.0 {
+            return -1.0;
+        }
+
+        let root = f32::sqrt(discriminant);
+        let near = (-ddemc - root) / ddd;
+        if near >= 0.0 {
+            return near;
+        }
+        return (-ddemc + root) / ddd;
+    }
+}
+
+/// Directions across a triangle that u and v increase in, worked out from how they change along its edges.
+/// None if the UVs are squashed to a line or a point.
+fn triangle_tangents(tr: &Triangle, uvs: [(f32, f32); 3]) -> Option<(Vec3, Vec3)> {
+    let [ua, ub, uc] = uvs;
+    let edge1 = tr.b - tr.a;
+    let edge2 = tr.c - tr.a;
+    let (du1, dv1) = (ub.0 - ua.0, ub.1 - ua.1);
+    let (du2, dv2) = (uc.0 - ua.0, uc.1 - ua.1);
+    let det = du1 * dv2 - du2 * dv1;
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let u_dir = (edge1 * dv2 - edge2 * dv1) * (1.0 / det);
+    let v_dir = (edge2 * du1 - edge1 * du2) * (1.0 / det);
+    return Some((u_dir, v_dir));
+}
+
+/// Intersects a triangle, giving back `close` instead if the triangle is missed or farther away
+/// # Arguements
+/// * 'tr' - the triangle to test
+/// * 'r' - the ray to test it against
+/// * 'close' - the closest hit found so far
+pub fn triangle_hit(prepared: PreparedTriangle, r: Ray, close: RayHit) -> RayHit {
+    let tr = prepared.triangle;
+    let (ab, ac) = prepared.edges;
+    let (a, b, c) = (ab.x, ab.y, ab.z);
+    let (d, e, f) = (ac.x, ac.y, ac.z);
     let g = r.direction_vector.x;
     let h = r.direction_vector.y;
     let i = r.direction_vector.z;
@@ -51,7 +550,7 @@ pub fn triangle_hit(tr: Triangle, r: Ray, close: RayHit) -> RayHit {
     }
 
     let gamma = (i * (a * k - j * b) + h * (j * c - a * l) + g * (b * l - k * c)) / m;
-    if gamma < 0.0 || gamma > 1.0 {
+    if !(0.0..=1.0).contains(&gamma) {
         return close;
     }
 
@@ -60,12 +559,38 @@ pub fn triangle_hit(tr: Triangle, r: Ray, close: RayHit) -> RayHit {
         return close;
     }
 
+    let flat_normal = prepared.flat_normal;
+    let backface = flat_normal * r.direction_vector > 0.0;
+    if backface && tr.cull_backfaces && !tr.double_sided {
+        return close;
+    }
+
+    let mut normal = match tr.vertex_normals {
+        Some([na, nb, nc]) => norm(na * (1.0 - beta - gamma) + nb * beta + nc * gamma),
+        None => flat_normal,
+    };
+    if backface && tr.double_sided {
+        normal = normal * -1.0;
+    }
+
+    let (u, v) = match tr.uvs {
+        Some([ua, ub, uc]) => (
+            ua.0 * (1.0 - beta - gamma) + ub.0 * beta + uc.0 * gamma,
+            ua.1 * (1.0 - beta - gamma) + ub.1 * beta + uc.1 * gamma,
+        ),
+        None => (0.0, 0.0),
+    };
+    let tangents = tr.uvs.and_then(|uvs| triangle_tangents(&tr, uvs));
+
     return RayHit {
         t,
         mat: tr.mat,
         intersect: r.start_pos + (r.direction_vector * t),
-        surface_normal: norm(cross(tr.b - tr.a, tr.c - tr.a)),
+        surface_normal: normal,
         id: tr.id,
+        u,
+        v,
+        tangents,
     };
 }
 
@@ -85,37 +610,1139 @@ pub struct RayHit {
     pub intersect: Vec3,
     pub surface_normal: Vec3,
     pub id: i8,
+    /// Texture coordinates of the hit, each from 0 to 1. Set by spheres and triangles that have them, everything
+    /// else is 0
+    pub u: f32,
+    pub v: f32,
+    /// Directions across the surface that u and v increase in, where the shape knows them. Not normalized.
+    pub tangents: Option<(Vec3, Vec3)>,
 }
 
+/// Distance along the ray to the nearest point in front of it where it crosses the sphere's surface. From inside
+/// that's the far wall. Negative when the sphere is missed or entirely behind the ray
 pub fn sphere_intersect(s: &Sphere, r: &Ray) -> f32 {
+    let emc = r.start_pos - s.center;
+    let ddemc = r.direction_vector * emc;
+    let c = (emc * emc) - (s.radius * s.radius);
+
+    // outside and heading away, so both crossings are behind the ray
+    if ddemc > 0.0 && c > 0.0 {
+        return -1.0;
+    }
+
+    let ddd = r.direction_vector * r.direction_vector;
+    let discriminant = (ddemc * ddemc) - ddd * c;
+    if discriminant < 0.0 {
+        return -1.0;
+    };
+
+    let root = f32::sqrt(discriminant);
+    let near = (-ddemc - root) / ddd;
+    if near >= 0.0 {
+        return near;
+    }
+    return (-ddemc + root) / ddd;
+}
+
+/// Like sphere_intersect, but returns both roots as (near, far) so callers can tell where the ray leaves
+pub fn sphere_interval(s: &Sphere, r: &Ray) -> Option<(f32, f32)> {
     let emc = r.start_pos - s.center;
     let ddd = r.direction_vector * r.direction_vector;
     let ddemc = r.direction_vector * emc;
     let discriminant = (ddemc * ddemc) - ddd * ((emc * emc) - (s.radius * s.radius));
 
     if discriminant < 0.0 {
-        return -1.0;
+        return None;
     };
 
-    let t1 = (-ddemc + f32::sqrt(discriminant)) / ddd;
-    let t2 = (-ddemc - f32::sqrt(discriminant)) / ddd;
+    let root = f32::sqrt(discriminant);
+    return Some(((-ddemc - root) / ddd, (-ddemc + root) / ddd));
+}
 
-    if t1 < 0.0 {
-        return t2;
-    } else if t2 < 0.0 {
-        return t1;
-    }
-    return f32::min(t1, t2);
+/// Where a point on a sphere lands on a texture wrapped around it, given its outward normal. The middle of the
+/// texture faces +z (the camera), the seam is at the back, and v runs from the top pole down, like image rows.
+fn sphere_uv(outward: Vec3) -> (f32, f32) {
+    let u = 0.5 + f32::atan2(outward.x, outward.z) / (2.0 * std::f32::consts::PI);
+    let v = f32::acos(outward.y.clamp(-1.0, 1.0)) / std::f32::consts::PI;
+    return (u, v);
 }
 
-pub fn sphere_hit(s: Sphere, r: Ray) -> RayHit {
+/// Where a ray hits a sphere, if it's in front of the ray and closer than `t_max`
+pub fn sphere_hit(s: Sphere, r: Ray, t_max: f32) -> Option<RayHit> {
+    let s = Sphere {
+        center: s.center + s.velocity * r.time,
+        ..s
+    };
+    // from inside, sphere_intersect already skips the root behind us and gives back the far wall
     let t_out = sphere_intersect(&s, &r);
+    // checked before working out the normal and texture coordinates, which most spheres tested never need
+    if !(t_out > 0.0 && t_out < t_max) {
+        return None;
+    }
     let intersection = r.start_pos + (r.direction_vector * t_out);
-    return RayHit {
+    let outward = norm(intersection - s.center);
+    let (u, v) = sphere_uv(outward);
+    // u runs around the y axis and v down from the top, so both vanish at the poles
+    let u_dir = vec(outward.z, 0.0, -outward.x);
+    let v_dir = cross(u_dir, outward);
+    return Some(RayHit {
         t: t_out,
         mat: s.mat,
         intersect: intersection,
-        surface_normal: norm(intersection - s.center),
+        surface_normal: if s.inverted { outward * -1.0 } else { outward },
         id: s.id,
+        u,
+        v,
+        tangents: Some((u_dir, v_dir)),
+    });
+}
+
+impl World {
+    pub fn add<T: Hittable + 'static>(&mut self, object: T) {
+        self.objects.push(Box::new(object));
+    }
+
+    pub fn build_bounds(&mut self) {
+        self.bounds = self.objects.iter().map(|o| o.bounds()).collect();
+    }
+}
+
+/// Smallest box holding both boxes
+pub fn aabb_union(a: Aabb, b: Aabb) -> Aabb {
+    return Aabb {
+        min: vec(
+            a.min.x.min(b.min.x),
+            a.min.y.min(b.min.y),
+            a.min.z.min(b.min.z),
+        ),
+        max: vec(
+            a.max.x.max(b.max.x),
+            a.max.y.max(b.max.y),
+            a.max.z.max(b.max.z),
+        ),
+    };
+}
+
+/// Box around some points, grown by `r` on every side plus a little slack for rounding
+fn points_bounds(points: &[Vec3], r: f32) -> Aabb {
+    let mut b = Aabb {
+        min: points[0],
+        max: points[0],
+    };
+    for p in &points[1..] {
+        b = aabb_union(b, Aabb { min: *p, max: *p });
+    }
+    let pad = r.abs() + 1e-4 * (1.0 + r.abs());
+    return Aabb {
+        min: b.min - vec(pad, pad, pad),
+        max: b.max + vec(pad, pad, pad),
+    };
+}
+
+fn triangles_bounds(triangles: &[PreparedTriangle]) -> Option<Aabb> {
+    return triangles
+        .iter()
+        .map(|prepared| triangle_bounds(&prepared.triangle))
+        .reduce(aabb_union);
+}
+
+fn solid_bounds(s: Solid) -> Aabb {
+    match s {
+        Solid::Sphere { center, radius } => return points_bounds(&[center], radius),
+        Solid::Cuboid { min, max } => return points_bounds(&[min, max], 0.0),
+    }
+}
+
+/// The box around a triangle, padded a hair so rounding can't make the box miss where the triangle hits
+pub fn triangle_bounds(tr: &Triangle) -> Aabb {
+    let min = vec(
+        tr.a.x.min(tr.b.x).min(tr.c.x),
+        tr.a.y.min(tr.b.y).min(tr.c.y),
+        tr.a.z.min(tr.b.z).min(tr.c.z),
+    );
+    let max = vec(
+        tr.a.x.max(tr.b.x).max(tr.c.x),
+        tr.a.y.max(tr.b.y).max(tr.c.y),
+        tr.a.z.max(tr.b.z).max(tr.c.z),
+    );
+    let pad = 1e-5 * (1.0 + mag(&(max - min)) + mag(&min) + mag(&max));
+    return Aabb {
+        min: min - vec(pad, pad, pad),
+        max: max + vec(pad, pad, pad),
+    };
+}
+
+/// Slab test. True if the ray passes through the box somewhere before `t_max`.
+pub fn ray_aabb_intersect(b: &Aabb, r: &Ray, t_max: f32) -> bool {
+    return ray_aabb_span(b, r, t_max).is_some();
+}
+
+/// Slab test giving where the ray enters and leaves the box, clipped to between 0 and `t_max`
+pub fn ray_aabb_span(b: &Aabb, r: &Ray, t_max: f32) -> Option<(f32, f32)> {
+    let mut t_near: f32 = 0.0;
+    let mut t_far = t_max;
+
+    for (start, dir, low, high) in [
+        (r.start_pos.x, r.direction_vector.x, b.min.x, b.max.x),
+        (r.start_pos.y, r.direction_vector.y, b.min.y, b.max.y),
+        (r.start_pos.z, r.direction_vector.z, b.min.z, b.max.z),
+    ] {
+        if dir == 0.0 {
+            // parallel to the slab, so it's all or nothing
+            if start < low || start > high {
+                return None;
+            }
+            continue;
+        }
+
+        let inv = 1.0 / dir;
+        let t_low = (low - start) * inv;
+        let t_high = (high - start) * inv;
+        t_near = t_near.max(t_low.min(t_high));
+        t_far = t_far.min(t_low.max(t_high));
+        if t_near > t_far {
+            return None;
+        }
+    }
+
+    return Some((t_near, t_far));
+}
+
+/// A RayHit flagged as a miss (negative t) for the given object
+fn no_hit(r: Ray, mat: Material, id: i8) -> RayHit {
+    return RayHit {
+        t: -1.0,
+        mat,
+        intersect: r.start_pos,
+        surface_normal: r.direction_vector,
+        id,
+        u: 0.0,
+        v: 0.0,
+        tangents: None,
+    };
+}
+
+pub fn plane_hit(p: Plane, r: Ray) -> RayHit {
+    let denom = p.normal * r.direction_vector;
+
+    // a ray parallel to the plane never hits it, and dividing by ~0 would give us inf/NaN
+    if denom.abs() < f32::EPSILON {
+        return no_hit(r, p.mat, p.id);
+    }
+
+    let t = ((p.point - r.start_pos) * p.normal) / denom;
+    return RayHit {
+        t,
+        mat: p.mat,
+        intersect: r.start_pos + (r.direction_vector * t),
+        surface_normal: p.normal,
+        id: p.id,
+        u: 0.0,
+        v: 0.0,
+        tangents: None,
+    };
+}
+
+/// Distance along a ray to a flat circular cap, or -1.0 if the ray misses it
+fn cap_intersect(center: Vec3, normal: Vec3, radius: f32, r: Ray) -> f32 {
+    let denom = normal * r.direction_vector;
+    if denom.abs() < f32::EPSILON {
+        return -1.0;
+    }
+
+    let t = ((center - r.start_pos) * normal) / denom;
+    let offset = r.start_pos + (r.direction_vector * t) - center;
+    if t < 0.0 || offset * offset > radius * radius {
+        return -1.0;
+    }
+    return t;
+}
+
+pub fn cylinder_hit(cy: Cylinder, r: Ray) -> RayHit {
+    let mut t = f32::MAX;
+    let mut normal = cy.axis;
+
+    // project the ray onto the plane perpendicular to the axis, which turns the side into a circle
+    let emb = r.start_pos - cy.base;
+    let d_perp = r.direction_vector - cy.axis * (r.direction_vector * cy.axis);
+    let emb_perp = emb - cy.axis * (emb * cy.axis);
+    let a = d_perp * d_perp;
+
+    // a ray running parallel to the axis can only ever hit the caps
+    if a > f32::EPSILON {
+        let b = d_perp * emb_perp;
+        let c = (emb_perp * emb_perp) - (cy.radius * cy.radius);
+        let discriminant = (b * b) - a * c;
+
+        if discriminant >= 0.0 {
+            let root = f32::sqrt(discriminant);
+            for side_t in [(-b - root) / a, (-b + root) / a] {
+                let height = (emb + r.direction_vector * side_t) * cy.axis;
+                if side_t > 0.0 && side_t < t && height >= 0.0 && height <= cy.height {
+                    t = side_t;
+                    let point = r.start_pos + (r.direction_vector * side_t);
+                    normal = norm(point - cy.base - cy.axis * height);
+                }
+            }
+        }
+    }
+
+    let top = cy.base + cy.axis * cy.height;
+    let bottom_t = cap_intersect(cy.base, cy.axis, cy.radius, r);
+    if bottom_t > 0.0 && bottom_t < t {
+        t = bottom_t;
+        normal = cy.axis * -1.0;
+    }
+
+    let top_t = cap_intersect(top, cy.axis, cy.radius, r);
+    if top_t > 0.0 && top_t < t {
+        t = top_t;
+        normal = cy.axis;
+    }
+
+    if t == f32::MAX {
+        return no_hit(r, cy.mat, cy.id);
+    }
+
+    return RayHit {
+        t,
+        mat: cy.mat,
+        intersect: r.start_pos + (r.direction_vector * t),
+        surface_normal: normal,
+        id: cy.id,
+        u: 0.0,
+        v: 0.0,
+        tangents: None,
+    };
+}
+
+pub fn cone_hit(co: Cone, r: Ray) -> RayHit {
+    let mut t = f32::MAX;
+    let mut normal = co.axis;
+
+    // cos^2 of the half angle
+    let slope = co.radius / co.height;
+    let cos2 = 1.0 / (1.0 + slope * slope);
+
+    let emv = r.start_pos - co.apex;
+    let dda = r.direction_vector * co.axis;
+    let emva = emv * co.axis;
+    let a = (dda * dda) - cos2 * (r.direction_vector * r.direction_vector);
+    let b = (dda * emva) - cos2 * (r.direction_vector * emv);
+    let c = (emva * emva) - cos2 * (emv * emv);
+
+    let mut roots = [-1.0, -1.0];
+    if a.abs() < f32::EPSILON {
+        // the ray runs parallel to the slant, so there is only the one crossing
+        if b.abs() > f32::EPSILON {
+            roots[0] = -c / (2.0 * b);
+        }
+    } else {
+        let discriminant = (b * b) - a * c;
+        if discriminant >= 0.0 {
+            let root = f32::sqrt(discriminant);
+            roots = [(-b - root) / a, (-b + root) / a];
+        }
+    }
+
+    for side_t in roots {
+        // anything behind the apex is on the mirrored "shadow cone" and gets thrown out here
+        let height = (emv + r.direction_vector * side_t) * co.axis;
+        if side_t > 0.0 && side_t < t && height >= 0.0 && height <= co.height {
+            t = side_t;
+            let to_point = r.start_pos + (r.direction_vector * side_t) - co.apex;
+            let outward = to_point * cos2 - co.axis * height;
+            normal = if mag(&outward) > f32::EPSILON {
+                norm(outward)
+            } else {
+                co.axis * -1.0
+            };
+        }
+    }
+
+    if co.capped {
+        let cap_t = cap_intersect(co.apex + co.axis * co.height, co.axis, co.radius, r);
+        if cap_t > 0.0 && cap_t < t {
+            t = cap_t;
+            normal = co.axis;
+        }
+    }
+
+    if t == f32::MAX {
+        return no_hit(r, co.mat, co.id);
+    }
+
+    return RayHit {
+        t,
+        mat: co.mat,
+        intersect: r.start_pos + (r.direction_vector * t),
+        surface_normal: normal,
+        id: co.id,
+        u: 0.0,
+        v: 0.0,
+        tangents: None,
+    };
+}
+
+pub fn disk_hit(d: Disk, r: Ray) -> RayHit {
+    let t = cap_intersect(d.center, d.normal, d.radius, r);
+    if t < 0.0 {
+        return no_hit(r, d.mat, d.id);
+    }
+
+    // face the normal back toward the ray so the underside doesn't shade black
+    let normal = if d.normal * r.direction_vector > 0.0 {
+        d.normal * -1.0
+    } else {
+        d.normal
+    };
+
+    return RayHit {
+        t,
+        mat: d.mat,
+        intersect: r.start_pos + (r.direction_vector * t),
+        surface_normal: normal,
+        id: d.id,
+        u: 0.0,
+        v: 0.0,
+        tangents: None,
+    };
+}
+
+pub fn torus_hit(to: Torus, r: Ray) -> RayHit {
+    // cheap reject against the bounding sphere before doing the quartic
+    let bounds = Sphere {
+        center: to.center,
+        velocity: vec(0.0, 0.0, 0.0),
+        radius: to.major_radius + to.minor_radius,
+        inverted: false,
+        mat: to.mat,
+        id: to.id,
+    };
+    let emc = r.start_pos - to.center;
+    let inside = emc * emc < bounds.radius * bounds.radius;
+    let bounds_t = sphere_intersect(&bounds, &r);
+    if bounds_t < 0.0 {
+        return no_hit(r, to.mat, to.id);
+    }
+
+    // start the ray at the bounding sphere so the quartic coefficients stay small
+    let skip = if inside { 0.0 } else { bounds_t as f64 };
+
+    let big_r2 = (to.major_radius * to.major_radius) as f64;
+    let small_r2 = (to.minor_radius * to.minor_radius) as f64;
+    let px = emc.x as f64 + r.direction_vector.x as f64 * skip;
+    let py = emc.y as f64 + r.direction_vector.y as f64 * skip;
+    let pz = emc.z as f64 + r.direction_vector.z as f64 * skip;
+    let dx = r.direction_vector.x as f64;
+    let dy = r.direction_vector.y as f64;
+    let dz = r.direction_vector.z as f64;
+
+    let pa = px * to.axis.x as f64 + py * to.axis.y as f64 + pz * to.axis.z as f64;
+    let da = dx * to.axis.x as f64 + dy * to.axis.y as f64 + dz * to.axis.z as f64;
+
+    // (|P|^2 - (R^2 + r^2))^2 - 4R^2r^2 + 4R^2(P.a)^2 = 0 with P = p + td
+    let alpha = dx * dx + dy * dy + dz * dz;
+    let beta = 2.0 * (px * dx + py * dy + pz * dz);
+    let gamma = (px * px + py * py + pz * pz) - (big_r2 + small_r2);
+
+    let roots = solve_quartic(
+        alpha * alpha,
+        2.0 * alpha * beta,
+        beta * beta + 2.0 * alpha * gamma + 4.0 * big_r2 * da * da,
+        2.0 * beta * gamma + 8.0 * big_r2 * pa * da,
+        gamma * gamma + 4.0 * big_r2 * pa * pa - 4.0 * big_r2 * small_r2,
+    );
+
+    let mut t = f64::MAX;
+    for root in roots {
+        if root + skip > 0.0 && root < t {
+            t = root;
+        }
+    }
+
+    if t == f64::MAX {
+        return no_hit(r, to.mat, to.id);
+    }
+
+    let t = (t + skip) as f32;
+    let intersection = r.start_pos + (r.direction_vector * t);
+
+    // the normal points away from the closest point on the ring running through the middle of the tube
+    let local = intersection - to.center;
+    let in_plane = local - to.axis * (local * to.axis);
+    let ring_point = if mag(&in_plane) > f32::EPSILON {
+        norm(in_plane) * to.major_radius
+    } else {
+        in_plane
+    };
+
+    return RayHit {
+        t,
+        mat: to.mat,
+        intersect: intersection,
+        surface_normal: norm(local - ring_point),
+        id: to.id,
+        u: 0.0,
+        v: 0.0,
+        tangents: None,
+    };
+}
+
+pub fn ellipsoid_hit(el: Ellipsoid, r: Ray) -> RayHit {
+    // squash the ray into the space where the ellipsoid is a unit sphere at the origin.
+    // the direction is left unnormalized so t means the same thing in both spaces
+    let unit = Sphere {
+        center: vec(0.0, 0.0, 0.0),
+        velocity: vec(0.0, 0.0, 0.0),
+        radius: 1.0,
+        inverted: false,
+        mat: el.mat,
+        id: el.id,
     };
+    let local = Ray {
+        start_pos: vec(
+            (r.start_pos.x - el.center.x) / el.radii.x,
+            (r.start_pos.y - el.center.y) / el.radii.y,
+            (r.start_pos.z - el.center.z) / el.radii.z,
+        ),
+        direction_vector: vec(
+            r.direction_vector.x / el.radii.x,
+            r.direction_vector.y / el.radii.y,
+            r.direction_vector.z / el.radii.z,
+        ),
+        time: r.time,
+    };
+
+    let t = sphere_intersect(&unit, &local);
+    if t < 0.0 {
+        return no_hit(r, el.mat, el.id);
+    }
+
+    // the unit sphere's normal is just the local hit point. Normals transform by the inverse transpose,
+    // which for a scale is dividing by the radii again
+    let local_normal = local.start_pos + (local.direction_vector * t);
+    let normal = vec(
+        local_normal.x / el.radii.x,
+        local_normal.y / el.radii.y,
+        local_normal.z / el.radii.z,
+    );
+
+    return RayHit {
+        t,
+        mat: el.mat,
+        intersect: r.start_pos + (r.direction_vector * t),
+        surface_normal: norm(normal),
+        id: el.id,
+        u: 0.0,
+        v: 0.0,
+        tangents: None,
+    };
+}
+
+pub fn mesh_hit(m: &Mesh, r: Ray) -> RayHit {
+    let mut closest = RayHit {
+        t: f32::MAX,
+        ..no_hit(r, m.mat, m.id)
+    };
+
+    for triangle in &m.triangles {
+        closest = triangle_hit(*triangle, r, closest);
+    }
+
+    if closest.t == f32::MAX {
+        return no_hit(r, m.mat, m.id);
+    }
+    return closest;
+}
+
+/// Where a ray is inside the given solid, ignoring whether that is in front of or behind the ray
+pub fn solid_span(solid: Solid, r: Ray) -> Option<Span> {
+    match solid {
+        Solid::Sphere { center, radius } => {
+            let s = Sphere {
+                center,
+                velocity: vec(0.0, 0.0, 0.0),
+                radius,
+                inverted: false,
+                mat: NO_MAT,
+                id: -1,
+            };
+            let (t_in, t_out) = sphere_interval(&s, &r)?;
+            let at = |t: f32| r.start_pos + (r.direction_vector * t);
+            return Some(Span {
+                t_in,
+                normal_in: norm(at(t_in) - center),
+                t_out,
+                normal_out: norm(at(t_out) - center),
+            });
+        }
+        Solid::Cuboid { min, max } => {
+            let mut span = Span {
+                t_in: f32::MIN,
+                normal_in: vec(0.0, 0.0, 0.0),
+                t_out: f32::MAX,
+                normal_out: vec(0.0, 0.0, 0.0),
+            };
+
+            // slab test, one axis at a time, keeping track of which face we crossed last/first
+            let axes = [
+                (
+                    r.start_pos.x,
+                    r.direction_vector.x,
+                    min.x,
+                    max.x,
+                    vec(1.0, 0.0, 0.0),
+                ),
+                (
+                    r.start_pos.y,
+                    r.direction_vector.y,
+                    min.y,
+                    max.y,
+                    vec(0.0, 1.0, 0.0),
+                ),
+                (
+                    r.start_pos.z,
+                    r.direction_vector.z,
+                    min.z,
+                    max.z,
+                    vec(0.0, 0.0, 1.0),
+                ),
+            ];
+            for (start, dir, low, high, axis) in axes {
+                if dir.abs() < f32::EPSILON {
+                    // parallel to this slab, so we are either always or never between its faces
+                    if start < low || start > high {
+                        return None;
+                    }
+                    continue;
+                }
+
+                let t_low = (low - start) / dir;
+                let t_high = (high - start) / dir;
+                let (near, far, near_normal) = if t_low < t_high {
+                    (t_low, t_high, axis * -1.0)
+                } else {
+                    (t_high, t_low, axis)
+                };
+
+                if near > span.t_in {
+                    span.t_in = near;
+                    span.normal_in = near_normal;
+                }
+                if far < span.t_out {
+                    span.t_out = far;
+                    span.normal_out = near_normal * -1.0;
+                }
+            }
+
+            if span.t_in > span.t_out {
+                return None;
+            }
+            return Some(span);
+        }
+    }
+}
+
+/// Combines the spans of two convex solids, giving back the (up to two) spans of the result in order
+fn combine_spans(op: CsgOp, left: Option<Span>, right: Option<Span>) -> Vec<Span> {
+    match op {
+        CsgOp::Union => match (left, right) {
+            (Some(a), Some(b)) => {
+                let (first, second) = if a.t_in <= b.t_in { (a, b) } else { (b, a) };
+                if second.t_in > first.t_out {
+                    return vec![first, second];
+                }
+                let mut merged = first;
+                if second.t_out > first.t_out {
+                    merged.t_out = second.t_out;
+                    merged.normal_out = second.normal_out;
+                }
+                return vec![merged];
+            }
+            (Some(a), None) | (None, Some(a)) => return vec![a],
+            (None, None) => return Vec::new(),
+        },
+        CsgOp::Intersection => {
+            let (Some(a), Some(b)) = (left, right) else {
+                return Vec::new();
+            };
+            let mut inner = a;
+            if b.t_in > a.t_in {
+                inner.t_in = b.t_in;
+                inner.normal_in = b.normal_in;
+            }
+            if b.t_out < a.t_out {
+                inner.t_out = b.t_out;
+                inner.normal_out = b.normal_out;
+            }
+            if inner.t_in > inner.t_out {
+                return Vec::new();
+            }
+            return vec![inner];
+        }
+        CsgOp::Difference => {
+            let Some(a) = left else {
+                return Vec::new();
+            };
+            let Some(b) = right else {
+                return vec![a];
+            };
+
+            // surfaces that come from the cut out shape face into it, so their normals flip
+            let mut spans = Vec::new();
+            if a.t_in < b.t_in {
+                let mut front = a;
+                if b.t_in < a.t_out {
+                    front.t_out = b.t_in;
+                    front.normal_out = b.normal_in * -1.0;
+                }
+                spans.push(front);
+            }
+            if b.t_out < a.t_out {
+                let mut back = a;
+                if b.t_out > a.t_in {
+                    back.t_in = b.t_out;
+                    back.normal_in = b.normal_out * -1.0;
+                }
+                spans.push(back);
+            }
+            return spans;
+        }
+    }
+}
+
+pub fn csg_hit(c: Csg, r: Ray) -> RayHit {
+    let spans = combine_spans(c.op, solid_span(c.left, r), solid_span(c.right, r));
+
+    // the first surface crossing in front of the ray is the hit, which may be an exit if we start inside
+    for span in spans {
+        let (t, normal) = if span.t_in > 0.0 {
+            (span.t_in, span.normal_in)
+        } else if span.t_out > 0.0 {
+            (span.t_out, span.normal_out)
+        } else {
+            continue;
+        };
+
+        return RayHit {
+            t,
+            mat: c.mat,
+            intersect: r.start_pos + (r.direction_vector * t),
+            surface_normal: normal,
+            id: c.id,
+            u: 0.0,
+            v: 0.0,
+            tangents: None,
+        };
+    }
+
+    return no_hit(r, c.mat, c.id);
+}
+
+/// Moves a triangle (and its vertex normals) by a transform. Its texture coordinates stay with their corners. Mirroring transforms swap the winding so the
+/// triangle keeps facing the same way relative to its shape.
+pub fn transform_triangle(tr: Triangle, m: &Mat4) -> Triangle {
+    let a = m.transform_point(tr.a);
+    let b = m.transform_point(tr.b);
+    let c = m.transform_point(tr.c);
+    let normals = tr.vertex_normals.map(|[na, nb, nc]| {
+        [
+            m.transform_normal(na),
+            m.transform_normal(nb),
+            m.transform_normal(nc),
+        ]
+    });
+
+    if m.determinant() < 0.0 {
+        return Triangle {
+            a,
+            b: c,
+            c: b,
+            vertex_normals: normals.map(|[na, nb, nc]| [na, nc, nb]),
+            uvs: tr.uvs.map(|[ua, ub, uc]| [ua, uc, ub]),
+            ..tr
+        };
+    }
+    return Triangle {
+        a,
+        b,
+        c,
+        vertex_normals: normals,
+        ..tr
+    };
+}
+
+pub fn instance_hit(inst: &Instance, r: Ray) -> RayHit {
+    // the direction isn't renormalized, which keeps t the same in both spaces
+    let local = Ray {
+        start_pos: inst.to_object.transform_point(r.start_pos),
+        direction_vector: inst.to_object.transform_vector(r.direction_vector),
+        time: r.time,
+    };
+
+    let mut closest = RayHit {
+        t: f32::MAX,
+        ..no_hit(r, NO_MAT, inst.id)
+    };
+    for triangle in inst.triangles.iter() {
+        closest = triangle_hit(*triangle, local, closest);
+    }
+
+    if closest.t == f32::MAX {
+        return no_hit(r, NO_MAT, inst.id);
+    }
+
+    return RayHit {
+        t: closest.t,
+        mat: closest.mat,
+        intersect: r.start_pos + (r.direction_vector * closest.t),
+        surface_normal: inst.to_world.transform_normal(closest.surface_normal),
+        id: inst.id,
+        u: closest.u,
+        v: closest.v,
+        tangents: closest.tangents.map(|(u_dir, v_dir)| {
+            (
+                inst.to_world.transform_vector(u_dir),
+                inst.to_world.transform_vector(v_dir),
+            )
+        }),
+    };
+}
+
+/// Turns a RayHit from the functions above into a hit, if it's in front of the ray and closer than `t_max`
+fn hit_within(hit: RayHit, t_max: f32) -> Option<RayHit> {
+    if hit.t > 0.0 && hit.t < t_max {
+        return Some(hit);
+    }
+    return None;
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, t_max: f32) -> Option<RayHit> {
+        return sphere_hit(*self, *ray, t_max);
+    }
+
+    fn id(&self) -> i8 {
+        return self.id;
+    }
+
+    fn as_sphere(&self) -> Option<&Sphere> {
+        return Some(self);
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        // everywhere it goes while the shutter's open
+        return Some(points_bounds(
+            &[self.center, self.center + self.velocity],
+            self.radius,
+        ));
+    }
+}
+
+impl Hittable for PreparedTriangle {
+    fn hit(&self, ray: &Ray, t_max: f32) -> Option<RayHit> {
+        let miss = RayHit {
+            t: t_max,
+            ..no_hit(*ray, self.triangle.mat, self.triangle.id)
+        };
+        return hit_within(triangle_hit(*self, *ray, miss), t_max);
+    }
+
+    fn id(&self) -> i8 {
+        return self.triangle.id;
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        return Some(triangle_bounds(&self.triangle));
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, t_max: f32) -> Option<RayHit> {
+        return hit_within(plane_hit(*self, *ray), t_max);
+    }
+
+    fn id(&self) -> i8 {
+        return self.id;
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        return None;
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, t_max: f32) -> Option<RayHit> {
+        return hit_within(cylinder_hit(*self, *ray), t_max);
+    }
+
+    fn id(&self) -> i8 {
+        return self.id;
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let top = self.base + self.axis * self.height;
+        return Some(points_bounds(&[self.base, top], self.radius));
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, t_max: f32) -> Option<RayHit> {
+        return hit_within(cone_hit(*self, *ray), t_max);
+    }
+
+    fn id(&self) -> i8 {
+        return self.id;
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let base = self.apex + self.axis * self.height;
+        return Some(points_bounds(&[self.apex, base], self.radius));
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, t_max: f32) -> Option<RayHit> {
+        return hit_within(disk_hit(*self, *ray), t_max);
+    }
+
+    fn id(&self) -> i8 {
+        return self.id;
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        return Some(points_bounds(&[self.center], self.radius));
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: &Ray, t_max: f32) -> Option<RayHit> {
+        return hit_within(torus_hit(*self, *ray), t_max);
+    }
+
+    fn id(&self) -> i8 {
+        return self.id;
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let reach = self.major_radius.abs() + self.minor_radius.abs();
+        return Some(points_bounds(&[self.center], reach));
+    }
+}
+
+impl Hittable for Ellipsoid {
+    fn hit(&self, ray: &Ray, t_max: f32) -> Option<RayHit> {
+        return hit_within(ellipsoid_hit(*self, *ray), t_max);
+    }
+
+    fn id(&self) -> i8 {
+        return self.id;
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let r = vec(self.radii.x.abs(), self.radii.y.abs(), self.radii.z.abs());
+        return Some(points_bounds(&[self.center - r, self.center + r], 0.0));
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray, t_max: f32) -> Option<RayHit> {
+        return hit_within(mesh_hit(self, *ray), t_max);
+    }
+
+    fn id(&self) -> i8 {
+        return self.id;
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        return triangles_bounds(&self.triangles);
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, t_max: f32) -> Option<RayHit> {
+        return hit_within(csg_hit(*self, *ray), t_max);
+    }
+
+    fn id(&self) -> i8 {
+        return self.id;
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        return Some(aabb_union(
+            solid_bounds(self.left),
+            solid_bounds(self.right),
+        ));
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, t_max: f32) -> Option<RayHit> {
+        return hit_within(instance_hit(self, *ray), t_max);
+    }
+
+    fn id(&self) -> i8 {
+        return self.id;
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let local = triangles_bounds(&self.triangles)?;
+        let mut corners = Vec::with_capacity(8);
+        for x in [local.min.x, local.max.x] {
+            for y in [local.min.y, local.max.y] {
+                for z in [local.min.z, local.max.z] {
+                    corners.push(self.to_world.transform_point(vec(x, y, z)));
+                }
+            }
+        }
+        return Some(points_bounds(&corners, 0.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec_math::vec;
+
+    fn ray(start: Vec3, direction: Vec3) -> Ray {
+        return Ray {
+            start_pos: start,
+            direction_vector: norm(direction),
+            time: 0.0,
+        };
+    }
+
+    /// A ring of radius 2 around the y axis, with a tube of radius 0.5
+    fn torus() -> Torus {
+        return Torus {
+            center: vec(0.0, 0.0, 0.0),
+            axis: vec(0.0, 1.0, 0.0),
+            major_radius: 2.0,
+            minor_radius: 0.5,
+            mat: NO_MAT,
+            id: 0,
+        };
+    }
+
+    #[test]
+    fn torus_miss() {
+        let hit = torus_hit(torus(), ray(vec(-5.0, 3.0, 0.0), vec(1.0, 0.0, 0.0)));
+        assert!(hit.t < 0.0);
+    }
+
+    #[test]
+    fn torus_through_the_hole() {
+        // straight down the axis, through the middle without touching the tube
+        let hit = torus_hit(torus(), ray(vec(0.0, 5.0, 0.0), vec(0.0, -1.0, 0.0)));
+        assert!(hit.t < 0.0);
+    }
+
+    #[test]
+    fn torus_through_the_tube() {
+        let hit = torus_hit(torus(), ray(vec(-5.0, 0.0, 0.0), vec(1.0, 0.0, 0.0)));
+        assert!((hit.t - 2.5).abs() < 1e-4, "{}", hit.t);
+        assert!(hit.surface_normal.x < -0.99, "{:?}", hit.surface_normal);
+    }
+
+    #[test]
+    fn torus_grazing_the_top() {
+        // skims along the top of the tube, touching it at x = -2 and x = 2 where the quartic has double roots
+        let hit = torus_hit(torus(), ray(vec(-5.0, 0.5, 0.0), vec(1.0, 0.0, 0.0)));
+        assert!((hit.t - 3.0).abs() < 1e-2, "{}", hit.t);
+        assert!(hit.surface_normal.y > 0.99, "{:?}", hit.surface_normal);
+    }
+
+    #[test]
+    fn ellipsoid_normal_matches_gradient() {
+        let el = Ellipsoid {
+            center: vec(1.0, 2.0, 3.0),
+            radii: vec(3.0, 1.0, 0.5),
+            mat: NO_MAT,
+            id: 0,
+        };
+        for from in [
+            vec(10.0, 4.0, 1.0),
+            vec(-6.0, 5.0, 7.0),
+            vec(2.0, -3.0, 8.0),
+            vec(1.5, 2.5, -4.0),
+        ] {
+            let hit = ellipsoid_hit(el, ray(from, el.center - from));
+            assert!(hit.t > 0.0);
+
+            // on the surface
+            let local = hit.intersect - el.center;
+            let (a, b, c) = (el.radii.x, el.radii.y, el.radii.z);
+            let level = (local.x / a).powi(2) + (local.y / b).powi(2) + (local.z / c).powi(2);
+            assert!((level - 1.0).abs() < 1e-3, "{}", level);
+
+            // the gradient of (x/a)^2 + (y/b)^2 + (z/c)^2 points straight out of the surface
+            let expected = norm(vec(local.x / (a * a), local.y / (b * b), local.z / (c * c)));
+            let n = hit.surface_normal;
+            assert!(
+                mag(&(n - expected)) < 1e-3,
+                "{:?} should be {:?}",
+                n,
+                expected
+            );
+        }
+    }
+
+    /// Hits a triangle in the z = 0 plane, wound to face +z, from in front or behind
+    fn triangle_from(front: bool, double_sided: bool, cull_backfaces: bool) -> RayHit {
+        let prepared = PreparedTriangle::new(Triangle {
+            a: vec(0.0, 0.0, 0.0),
+            b: vec(1.0, 0.0, 0.0),
+            c: vec(0.0, 1.0, 0.0),
+            vertex_normals: None,
+            uvs: None,
+            double_sided,
+            cull_backfaces,
+            mat: NO_MAT,
+            id: 1,
+        });
+        assert!(prepared.flat_normal.z > 0.99);
+        let side = if front { 1.0 } else { -1.0 };
+        let r = ray(vec(0.25, 0.25, 2.0 * side), vec(0.0, 0.0, -side));
+        let close = RayHit {
+            t: f32::MAX,
+            ..no_hit(r, NO_MAT, -1)
+        };
+        return triangle_hit(prepared, r, close);
+    }
+
+    #[test]
+    fn triangle_front() {
+        for (double_sided, cull_backfaces) in [(false, false), (true, false), (false, true)] {
+            let hit = triangle_from(true, double_sided, cull_backfaces);
+            assert_eq!(hit.id, 1);
+            assert!((hit.t - 2.0).abs() < 1e-5);
+            assert!(hit.surface_normal.z > 0.99);
+        }
+    }
+
+    #[test]
+    fn triangle_back_keeps_its_normal() {
+        let hit = triangle_from(false, false, false);
+        assert_eq!(hit.id, 1);
+        assert!((hit.t - 2.0).abs() < 1e-5);
+        assert!(hit.surface_normal.z > 0.99);
+    }
+
+    #[test]
+    fn triangle_back_double_sided_faces_the_ray() {
+        // and double sided wins over culling
+        for cull_backfaces in [false, true] {
+            let hit = triangle_from(false, true, cull_backfaces);
+            assert_eq!(hit.id, 1);
+            assert!((hit.t - 2.0).abs() < 1e-5);
+            assert!(hit.surface_normal.z < -0.99);
+        }
+    }
+
+    #[test]
+    fn triangle_back_culled() {
+        let hit = triangle_from(false, false, true);
+        assert_eq!(hit.id, -1);
+    }
 }
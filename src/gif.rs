@@ -0,0 +1,153 @@
+// Writes a run of frames into one animated GIF, a frame at a time as they're rendered, so they never all have to be
+// held at once.
+
+use image::error::{EncodingError, ImageFormatHint, LimitError, LimitErrorKind};
+use image::{ImageError, ImageFormat, ImageResult};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// How carefully each frame's colors are picked, from 1 for the best to 30 for the quickest
+const QUANTIZE_SPEED: i32 = 10;
+
+pub struct GifWriter {
+    /// Where the GIF goes until the first frame, which sets how big it is, starts the encoder
+    out: Option<BufWriter<File>>,
+    encoder: Option<gif::Encoder<BufWriter<File>>>,
+    /// How long each frame is shown for, in hundredths of a second
+    delay: u16,
+    path: String,
+    /// Where it's written until it's finished
+    partial: String,
+}
+
+impl GifWriter {
+    /// Starts a GIF that loops forever, making any folders it goes in that don't exist yet. It's written alongside
+    /// `path` and only moved into place once it's finished, like other images.
+    /// # Arguements
+    /// * 'path' - where the finished GIF goes
+    /// * 'delay' - how long each frame is shown for, in milliseconds. GIFs only keep hundredths of a second
+    pub fn create(path: &str, delay: u32) -> ImageResult<GifWriter> {
+        if let Some(folder) = Path::new(path).parent() {
+            std::fs::create_dir_all(folder)?;
+        }
+        let partial = format!("{}.partial", path);
+        let out = BufWriter::new(File::create(&partial)?);
+        return Ok(GifWriter {
+            out: Some(out),
+            encoder: None,
+            delay: ((delay + 5) / 10).min(u16::MAX as u32) as u16,
+            path: path.to_string(),
+            partial,
+        });
+    }
+
+    /// Adds the next frame. A GIF frame can only have 256 colors, so the best ones for this frame are picked and
+    /// the frame is dithered with them, which keeps smooth gradients from breaking up into bands.
+    pub fn add(&mut self, image: &image::RgbImage) -> ImageResult<()> {
+        let (Ok(width), Ok(height)) = (u16::try_from(image.width()), u16::try_from(image.height()))
+        else {
+            return Err(ImageError::Limits(LimitError::from_kind(
+                LimitErrorKind::DimensionError,
+            )));
+        };
+        // the first frame starts the GIF off at its size
+        if let Some(out) = self.out.take() {
+            let mut encoder = gif::Encoder::new(out, width, height, &[]).map_err(gif_error)?;
+            encoder
+                .set_repeat(gif::Repeat::Infinite)
+                .map_err(gif_error)?;
+            self.encoder = Some(encoder);
+        }
+        let Some(encoder) = self.encoder.as_mut() else {
+            return Err(gif_error(
+                std::io::Error::other("the GIF couldn't be started").into(),
+            ));
+        };
+
+        let mut frame = image::DynamicImage::ImageRgb8(image.clone()).into_rgba8();
+        let palette = color_quant::NeuQuant::new(QUANTIZE_SPEED, 256, frame.as_raw());
+        image::imageops::dither(&mut frame, &palette);
+        // every pixel is one of the palette's colors now, so each can be stored as which one it is
+        let indices = image::imageops::index_colors(&frame, &palette);
+        let mut frame = gif::Frame::from_palette_pixels(
+            width,
+            height,
+            indices.as_raw(),
+            &palette.color_map_rgb(),
+            None,
+        );
+        frame.delay = self.delay;
+        return encoder.write_frame(&frame).map_err(gif_error);
+    }
+
+    /// Ends the GIF and moves it into place. Anything that goes wrong writing the end of it is reported, and the
+    /// unfinished file is left where it was rather than moved.
+    pub fn finish(self) -> ImageResult<()> {
+        let Some(encoder) = self.encoder else {
+            return Err(gif_error(
+                std::io::Error::other("no frames were added").into(),
+            ));
+        };
+        // writes the end of the GIF, then flushes it all out to the file
+        let file = encoder
+            .into_inner()?
+            .into_inner()
+            .map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        std::fs::rename(&self.partial, &self.path)?;
+        return Ok(());
+    }
+}
+
+/// Reports something the GIF encoder couldn't do as an image error, like the rest of saving
+fn gif_error(e: gif::EncodingError) -> ImageError {
+    return ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Gif),
+        e,
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::AnimationDecoder;
+
+    #[test]
+    fn frames_are_written_and_moved_into_place() {
+        let path = std::env::temp_dir().join(format!("raytracer_gif_{}.gif", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut gif = GifWriter::create(path, 120).unwrap();
+        for shade in [0, 128, 255] {
+            // a gradient across each frame, darker or lighter each time
+            let frame =
+                image::RgbImage::from_fn(16, 8, |x, _| image::Rgb([shade, (x * 16) as u8, 64]));
+            gif.add(&frame).unwrap();
+        }
+        assert!(!Path::new(path).exists());
+        gif.finish().unwrap();
+        assert!(!Path::new(&format!("{}.partial", path)).exists());
+
+        let decoder = image::codecs::gif::GifDecoder::new(File::open(path).unwrap()).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(frames.len(), 3);
+        for frame in &frames {
+            assert_eq!(frame.buffer().dimensions(), (16, 8));
+            assert_eq!(frame.delay().numer_denom_ms(), (120, 1));
+        }
+        // the middle frame's red is kept close, whatever colors were picked for it
+        let red = frames[1].buffer().get_pixel(8, 4).0[0];
+        assert!(red.abs_diff(128) < 16, "{}", red);
+    }
+
+    #[test]
+    fn finishing_without_frames_fails() {
+        let path = std::env::temp_dir().join(format!("raytracer_empty_{}.gif", std::process::id()));
+        let path = path.to_str().unwrap();
+        let gif = GifWriter::create(path, 40).unwrap();
+        assert!(gif.finish().is_err());
+        assert!(!Path::new(path).exists());
+        std::fs::remove_file(format!("{}.partial", path)).unwrap();
+    }
+}
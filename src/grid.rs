@@ -0,0 +1,204 @@
+// Uniform grid over everything in a World, walked cell by cell along the ray (3D DDA).
+// Simpler than the BVH and often quicker for scenes that are spread evenly, like terrain.
+
+use crate::bvh::{axis_value, blocks, consider};
+use crate::geometry::{aabb_union, ray_aabb_span, Aabb, RayHit, World};
+use crate::vec_math::Ray;
+
+/// Roughly how many cells to make per object
+const CELLS_PER_OBJECT: f32 = 2.0;
+/// Cap on cells along any one axis, so a few huge objects can't eat all the memory
+const MAX_RESOLUTION: usize = 128;
+
+#[derive(Debug, Clone, Default)]
+pub struct Grid {
+    bounds: Option<Aabb>,
+    resolution: [usize; 3],
+    cell_size: [f32; 3],
+    /// Indices into the world's objects for every cell, x fastest then y then z
+    cells: Vec<Vec<usize>>,
+    /// Things with no finite bounds (planes), tested against every ray
+    unbounded: Vec<usize>,
+}
+
+fn cell_index(grid: &Grid, cell: [usize; 3]) -> usize {
+    return cell[0] + grid.resolution[0] * (cell[1] + grid.resolution[1] * cell[2]);
+}
+
+/// The cell along `axis` containing `value`, clamped into the grid
+fn cell_coord(grid: &Grid, bounds: &Aabb, value: f32, axis: usize) -> usize {
+    let offset = (value - axis_value(bounds.min, axis)) / grid.cell_size[axis];
+    return (offset.max(0.0) as usize).min(grid.resolution[axis] - 1);
+}
+
+/// Bins everything currently in the world into cells. Objects spanning several cells go in each of them.
+///
+/// # Arguements
+/// * 'world' - the parsed scene, with its bounds already built
+pub fn build_grid(world: &World) -> Grid {
+    let mut grid = Grid::default();
+
+    let mut bounded: Vec<(usize, Aabb)> = Vec::new();
+    for (i, bounds) in world.bounds.iter().enumerate() {
+        match bounds {
+            Some(b) => bounded.push((i, *b)),
+            None => grid.unbounded.push(i),
+        }
+    }
+
+    let bounds = match bounded.iter().map(|(_, b)| *b).reduce(aabb_union) {
+        Some(b) => b,
+        None => return grid,
+    };
+
+    // pick cells that are about cube shaped, with around CELLS_PER_OBJECT of them per object
+    let extent = bounds.max - bounds.min;
+    let volume = (extent.x * extent.y * extent.z).max(f32::MIN_POSITIVE);
+    let per_unit = (CELLS_PER_OBJECT * bounded.len() as f32 / volume).cbrt();
+    for axis in 0..3 {
+        let length = axis_value(extent, axis);
+        let cells = ((length * per_unit).round() as usize).clamp(1, MAX_RESOLUTION);
+        grid.resolution[axis] = cells;
+        grid.cell_size[axis] = length / cells as f32;
+    }
+
+    grid.cells = vec![Vec::new(); grid.resolution[0] * grid.resolution[1] * grid.resolution[2]];
+    for (i, b) in &bounded {
+        let low = [0, 1, 2].map(|axis| cell_coord(&grid, &bounds, axis_value(b.min, axis), axis));
+        let high = [0, 1, 2].map(|axis| cell_coord(&grid, &bounds, axis_value(b.max, axis), axis));
+        for z in low[2]..=high[2] {
+            for y in low[1]..=high[1] {
+                for x in low[0]..=high[0] {
+                    let index = cell_index(&grid, [x, y, z]);
+                    grid.cells[index].push(*i);
+                }
+            }
+        }
+    }
+
+    grid.bounds = Some(bounds);
+    return grid;
+}
+
+/// Same answer as testing every object in the world in order, see `bvh_closest_hit`. None if nothing was hit.
+///
+/// # Arguements
+/// * 'grid' - grid built from `world`
+/// * 'ray' - ray to trace, which can start inside or outside the grid
+/// * 'id' - id of an object to ignore, usually the one the ray is leaving
+/// * 'world' - the objects themselves
+pub fn grid_closest_hit(grid: &Grid, ray: Ray, id: i8, world: &World) -> Option<RayHit> {
+    let mut best: Option<(RayHit, usize)> = None;
+
+    for &i in &grid.unbounded {
+        consider(world, i, ray, id, &mut best);
+    }
+
+    walk(grid, ray, f32::MAX, &mut |cell, t_exit| {
+        // an object listed in several cells just gets tested again, which hands back the same hit and changes nothing
+        for &i in cell {
+            consider(world, i, ray, id, &mut best);
+        }
+        // a hit inside this cell can't be beaten by anything further along, give or take some rounding
+        return match &best {
+            Some((hit, _)) => hit.t < t_exit - 1e-4 * (1.0 + t_exit.abs()),
+            None => false,
+        };
+    });
+
+    return best.map(|(hit, _)| hit);
+}
+
+/// Whether anything is hit closer than `t_max` that `stop` says counts, stopping at the first one, see `bvh_any_hit`
+///
+/// # Arguements
+/// * 'grid' - grid built from `world`
+/// * 'ray' - ray to trace, which can start inside or outside the grid
+/// * 't_max' - how far along the ray to look
+/// * 'id' - id of an object to ignore, usually the one the ray is leaving
+/// * 'world' - the objects themselves
+/// * 'stop' - whether a hit counts, or should be looked past
+pub fn grid_any_hit(
+    grid: &Grid,
+    ray: Ray,
+    t_max: f32,
+    id: i8,
+    world: &World,
+    stop: &mut dyn FnMut(&RayHit) -> bool,
+) -> bool {
+    for &i in &grid.unbounded {
+        if blocks(world, i, ray, t_max, id, stop) {
+            return true;
+        }
+    }
+
+    let mut found = false;
+    walk(grid, ray, t_max, &mut |cell, _| {
+        // an object listed in several cells can be handed to `stop` more than once
+        found = cell.iter().any(|&i| blocks(world, i, ray, t_max, id, stop));
+        return found;
+    });
+    return found;
+}
+
+/// Steps through the cells the ray passes, nearest first, handing each one's objects and how far along the ray it
+/// ends to `visit` until it says to stop
+/// # Arguements
+/// * 'grid' - grid to walk
+/// * 'ray' - ray to follow
+/// * 't_max' - how far along the ray to go
+/// * 'visit' - looks at a cell, giving back true to stop there
+fn walk(grid: &Grid, ray: Ray, t_max: f32, visit: &mut dyn FnMut(&[usize], f32) -> bool) {
+    let Some(bounds) = &grid.bounds else {
+        return;
+    };
+    let Some((t_enter, t_leave)) = ray_aabb_span(bounds, &ray, t_max) else {
+        return;
+    };
+
+    // set up the walk from wherever the ray first touches the grid
+    let entry = ray.start_pos + ray.direction_vector * t_enter;
+    let mut cell = [0, 1, 2].map(|axis| cell_coord(grid, bounds, axis_value(entry, axis), axis));
+    let mut step = [0_i64; 3];
+    let mut t_next = [f32::MAX; 3];
+    let mut t_delta = [f32::MAX; 3];
+    for axis in 0..3 {
+        let dir = axis_value(ray.direction_vector, axis);
+        let start = axis_value(ray.start_pos, axis);
+        let cell_min = axis_value(bounds.min, axis) + cell[axis] as f32 * grid.cell_size[axis];
+        if dir > 0.0 {
+            step[axis] = 1;
+            t_delta[axis] = grid.cell_size[axis] / dir;
+            t_next[axis] = (cell_min + grid.cell_size[axis] - start) / dir;
+        } else if dir < 0.0 {
+            step[axis] = -1;
+            t_delta[axis] = -grid.cell_size[axis] / dir;
+            t_next[axis] = (cell_min - start) / dir;
+        }
+    }
+
+    loop {
+        let axis = if t_next[0] <= t_next[1] && t_next[0] <= t_next[2] {
+            0
+        } else if t_next[1] <= t_next[2] {
+            1
+        } else {
+            2
+        };
+        let t_exit = t_next[axis].min(t_leave);
+
+        if visit(&grid.cells[cell_index(grid, cell)], t_exit) {
+            return;
+        }
+
+        if t_next[axis] > t_leave {
+            return;
+        }
+        let next = cell[axis] as i64 + step[axis];
+        if next < 0 || next >= grid.resolution[axis] as i64 {
+            return;
+        }
+        cell[axis] = next as usize;
+        t_next[axis] += t_delta[axis];
+    }
+}
@@ -0,0 +1,22 @@
+// The ray tracer itself, shared by the command line program and the benchmarks
+#![allow(clippy::needless_return)]
+
+pub mod animation;
+pub mod aov;
+pub mod bvh;
+pub mod denoise;
+pub mod geometry;
+pub mod gif;
+pub mod grid;
+pub mod loaders;
+pub mod metadata;
+pub mod noise;
+pub mod ppm;
+pub mod progress;
+pub mod rng;
+pub mod sampling;
+pub mod scene;
+pub mod solver;
+pub mod stats;
+pub mod tiles;
+pub mod vec_math;
@@ -0,0 +1,105 @@
+// Builds terrain out of a grayscale image, where brighter pixels are higher.
+
+use crate::geometry::{Material, Mesh, PreparedTriangle, Triangle};
+use crate::vec_math::{vec, Vec3};
+use std::path::Path;
+
+/// Width and depth in cells of each chunk the terrain is split into
+const CHUNK_CELLS: u32 = 16;
+
+/// Loads a heightmap image as a grid of triangles, two per cell, grouped into chunked meshes.
+/// Heights come from the green channel.
+/// # Arguements
+/// * 'path' - the image to load
+/// * 'origin' - where the (0, 0) corner of the image sits at zero height
+/// * 'size' - how far the terrain stretches along x and z, and how tall a full green value is along y
+/// * 'mat' - material given to every triangle
+/// * 'id' - id shared by every chunk, so the terrain is treated as one object
+/// * 'skip_zero' - leave out cells whose four corners are all at zero height
+pub fn load_heightmap(
+    path: &Path,
+    origin: Vec3,
+    size: Vec3,
+    mat: Material,
+    id: i8,
+    skip_zero: bool,
+) -> Result<Vec<Mesh>, String> {
+    let img = image::open(path)
+        .map_err(|err| format!("could not read {}: {}", path.display(), err))?
+        .to_rgb8();
+
+    let (width, height) = img.dimensions();
+    if width < 2 || height < 2 {
+        return Err(format!(
+            "{} is too small to make terrain from",
+            path.display()
+        ));
+    }
+
+    let level = |x: u32, z: u32| img.get_pixel(x, z)[1];
+    let point = |x: u32, z: u32| {
+        vec(
+            origin.x + size.x * x as f32 / (width - 1) as f32,
+            origin.y + size.y * level(x, z) as f32 / 255.0,
+            origin.z + size.z * z as f32 / (height - 1) as f32,
+        )
+    };
+
+    let mut chunks = Vec::new();
+    for chunk_z in (0..height - 1).step_by(CHUNK_CELLS as usize) {
+        for chunk_x in (0..width - 1).step_by(CHUNK_CELLS as usize) {
+            let mut triangles = Vec::new();
+
+            for z in chunk_z..u32::min(chunk_z + CHUNK_CELLS, height - 1) {
+                for x in chunk_x..u32::min(chunk_x + CHUNK_CELLS, width - 1) {
+                    let flat = level(x, z) == 0
+                        && level(x + 1, z) == 0
+                        && level(x, z + 1) == 0
+                        && level(x + 1, z + 1) == 0;
+                    if skip_zero && flat {
+                        continue;
+                    }
+
+                    // wound so the normals face up the y axis
+                    let p00 = point(x, z);
+                    let p10 = point(x + 1, z);
+                    let p01 = point(x, z + 1);
+                    let p11 = point(x + 1, z + 1);
+                    triangles.push(Triangle {
+                        a: p00,
+                        b: p01,
+                        c: p10,
+                        vertex_normals: None,
+                        uvs: None,
+                        double_sided: false,
+                        cull_backfaces: false,
+                        mat,
+                        id,
+                    });
+                    triangles.push(Triangle {
+                        a: p10,
+                        b: p01,
+                        c: p11,
+                        vertex_normals: None,
+                        uvs: None,
+                        double_sided: false,
+                        cull_backfaces: false,
+                        mat,
+                        id,
+                    });
+                }
+            }
+
+            if !triangles.is_empty() {
+                chunks.push(Mesh {
+                    triangles: triangles.into_iter().map(PreparedTriangle::new).collect(),
+                    double_sided: false,
+                    mat,
+                    id,
+                });
+            }
+        }
+    }
+
+    return Ok(chunks);
+}
@@ -0,0 +1,5 @@
+// Importers for mesh formats that can be pulled into a scene file
+
+pub mod heightmap;
+pub mod obj;
+pub mod stl;
@@ -0,0 +1,118 @@
+// Minimal Wavefront OBJ reader. Only vertex positions, texture coordinates, vertex normals, and faces are used,
+// everything else is ignored.
+
+use crate::geometry::{Material, Triangle};
+use crate::vec_math::{norm, vec, Vec3};
+use std::fs;
+use std::path::Path;
+
+/// Pulls one index out of a face corner token like `3`, `3/1`, `3//2`, or `-1`
+/// # Arguements
+/// * 'token' - one corner of an `f` record
+/// * 'slot' - which of the slash separated indices to read: 0 for the position, 1 for the texture coordinate,
+///   2 for the normal
+/// * 'count' - how many of that record have been read so far, needed for negative (relative) indices
+fn parse_index(token: &str, slot: usize, count: usize) -> Option<usize> {
+    let index = token.split('/').nth(slot)?.parse::<i64>().ok()?;
+    if index > 0 && (index as usize) <= count {
+        return Some(index as usize - 1);
+    } else if index < 0 && (-index as usize) <= count {
+        return Some((count as i64 + index) as usize);
+    }
+    return None;
+}
+
+fn parse_coords<'a>(tokens: impl Iterator<Item = &'a str>) -> Vec3 {
+    let mut coords = tokens.map(|val| val.parse::<f32>().unwrap_or(0.0));
+    let x = coords.next().unwrap_or(0.0);
+    let y = coords.next().unwrap_or(0.0);
+    let z = coords.next().unwrap_or(0.0);
+    return vec(x, y, z);
+}
+
+/// Reads an OBJ file into triangles, fanning out any faces with more than three corners
+/// # Arguements
+/// * 'path' - the OBJ file to load
+/// * 'mat' - material given to every triangle
+/// * 'id' - id given to every triangle
+/// * 'double_sided' - whether every triangle shades the same from both sides
+pub fn load_obj(
+    path: &Path,
+    mat: Material,
+    id: i8,
+    double_sided: bool,
+) -> Result<Vec<Triangle>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+
+    let mut vertices: Vec<Vec3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(f32, f32)> = Vec::new();
+    let mut triangles: Vec<Triangle> = Vec::new();
+    let mut skipped = 0;
+
+    for (line_num, line) in contents.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => vertices.push(parse_coords(tokens)),
+            Some("vn") => normals.push(norm(parse_coords(tokens))),
+            Some("vt") => {
+                // OBJ puts v = 0 at the bottom of the image, we count from the top row
+                let uv = parse_coords(tokens);
+                uvs.push((uv.x, 1.0 - uv.y));
+            }
+            Some("f") => {
+                let tokens: Vec<&str> = tokens.collect();
+                let corners: Option<Vec<usize>> = tokens
+                    .iter()
+                    .map(|token| parse_index(token, 0, vertices.len()))
+                    .collect();
+                // normals are all or nothing, a face with any missing falls back to flat shading
+                let corner_normals: Option<Vec<Vec3>> = tokens
+                    .iter()
+                    .map(|token| parse_index(token, 2, normals.len()).map(|n| normals[n]))
+                    .collect();
+                // same for texture coordinates, a face with any missing gets none
+                let corner_uvs: Option<Vec<(f32, f32)>> = tokens
+                    .iter()
+                    .map(|token| parse_index(token, 1, uvs.len()).map(|n| uvs[n]))
+                    .collect();
+
+                match corners {
+                    Some(corners) if corners.len() >= 3 => {
+                        for i in 1..corners.len() - 1 {
+                            triangles.push(Triangle {
+                                a: vertices[corners[0]],
+                                b: vertices[corners[i]],
+                                c: vertices[corners[i + 1]],
+                                vertex_normals: corner_normals
+                                    .as_ref()
+                                    .map(|n| [n[0], n[i], n[i + 1]]),
+                                uvs: corner_uvs.as_ref().map(|uv| [uv[0], uv[i], uv[i + 1]]),
+                                double_sided,
+                                cull_backfaces: false,
+                                mat,
+                                id,
+                            });
+                        }
+                    }
+                    _ => {
+                        println!(
+                            "Skipping bad face on line {} of {}",
+                            line_num + 1,
+                            path.display()
+                        );
+                        skipped += 1;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if skipped > 0 {
+        println!("Skipped {} faces in {}", skipped, path.display());
+    }
+
+    return Ok(triangles);
+}
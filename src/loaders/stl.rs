@@ -0,0 +1,141 @@
+// STL reader for both the binary and ASCII flavours. Facet normals in the file are ignored and
+// worked out from the winding when the triangle is prepared, like every other triangle.
+
+use crate::geometry::{Material, Triangle};
+use crate::vec_math::{cross, mag, vec, Vec3};
+use std::fs;
+use std::path::Path;
+
+/// Size of the binary header that comes before the facet count
+const HEADER_LEN: usize = 80;
+/// Bytes per binary facet: normal + 3 vertices as f32s, then a u16 attribute count
+const FACET_LEN: usize = 50;
+
+fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+    return f32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ]);
+}
+
+fn read_vec(bytes: &[u8], offset: usize) -> Vec3 {
+    return vec(
+        read_f32(bytes, offset),
+        read_f32(bytes, offset + 4),
+        read_f32(bytes, offset + 8),
+    );
+}
+
+/// Binary files are recognised by their size matching the facet count in the header, since plenty of
+/// exporters write "solid" at the start of binary headers too
+fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.len() < HEADER_LEN + 4 {
+        return false;
+    }
+    let count = u32::from_le_bytes([
+        bytes[HEADER_LEN],
+        bytes[HEADER_LEN + 1],
+        bytes[HEADER_LEN + 2],
+        bytes[HEADER_LEN + 3],
+    ]) as usize;
+    return bytes.len() == HEADER_LEN + 4 + count * FACET_LEN;
+}
+
+fn parse_binary(bytes: &[u8]) -> Vec<[Vec3; 3]> {
+    let count = (bytes.len() - HEADER_LEN - 4) / FACET_LEN;
+    let mut facets = Vec::with_capacity(count);
+
+    for i in 0..count {
+        // skip the 12 byte normal at the start of each facet
+        let start = HEADER_LEN + 4 + i * FACET_LEN + 12;
+        facets.push([
+            read_vec(bytes, start),
+            read_vec(bytes, start + 12),
+            read_vec(bytes, start + 24),
+        ]);
+    }
+
+    return facets;
+}
+
+fn parse_ascii(text: &str) -> Vec<[Vec3; 3]> {
+    let mut facets = Vec::new();
+    let mut corners: Vec<Vec3> = Vec::new();
+
+    for line in text.lines() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("vertex") => {
+                let mut coords = tokens.map(|val| val.parse::<f32>().unwrap_or(0.0));
+                let x = coords.next().unwrap_or(0.0);
+                let y = coords.next().unwrap_or(0.0);
+                let z = coords.next().unwrap_or(0.0);
+                corners.push(vec(x, y, z));
+            }
+            Some("endfacet") => {
+                if corners.len() == 3 {
+                    facets.push([corners[0], corners[1], corners[2]]);
+                }
+                corners.clear();
+            }
+            _ => {}
+        }
+    }
+
+    return facets;
+}
+
+/// Reads a binary or ASCII STL file into triangles, dropping any zero area facets
+/// # Arguements
+/// * 'path' - the STL file to load
+/// * 'mat' - material given to every triangle
+/// * 'id' - id given to every triangle
+/// * 'double_sided' - whether every triangle shades the same from both sides
+pub fn load_stl(
+    path: &Path,
+    mat: Material,
+    id: i8,
+    double_sided: bool,
+) -> Result<Vec<Triangle>, String> {
+    let bytes =
+        fs::read(path).map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+
+    let facets = if is_binary(&bytes) {
+        parse_binary(&bytes)
+    } else {
+        parse_ascii(&String::from_utf8_lossy(&bytes))
+    };
+
+    let mut triangles = Vec::with_capacity(facets.len());
+    let mut skipped = 0;
+    for [a, b, c] in facets {
+        // a degenerate facet would give triangle_hit a NaN normal
+        if mag(&cross(b - a, c - a)) < f32::EPSILON {
+            skipped += 1;
+            continue;
+        }
+        triangles.push(Triangle {
+            a,
+            b,
+            c,
+            vertex_normals: None,
+            uvs: None,
+            double_sided,
+            cull_backfaces: false,
+            mat,
+            id,
+        });
+    }
+
+    if skipped > 0 {
+        println!(
+            "Skipped {} degenerate facets in {}",
+            skipped,
+            path.display()
+        );
+    }
+
+    return Ok(triangles);
+}
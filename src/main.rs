@@ -1,161 +1,32 @@
 // using https://github.com/image-rs/image | https://docs.rs/crate/image/latest
-
-mod geometry;
-mod vec_math;
-
-use geometry::{sphere_hit, triangle_hit, RayHit, Sphere, Triangle};
+#![allow(clippy::needless_return)]
+
+use raytracer::animation::Frames;
+use raytracer::aov::{self, Aovs, DepthScale};
+use raytracer::geometry::SpecularModel;
+use raytracer::metadata::{self, Metadata};
+use raytracer::scene::{
+    self, Accel, Mode, Projection, Render, RenderSettings, Sampler, Scene, Stereo, Tonemap,
+};
+use raytracer::vec_math::{vec, Vec3};
+use raytracer::{gif, ppm, stats};
 use std::collections::VecDeque;
 use std::env;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
-use std::num::ParseIntError;
-use vec_math::{mag, norm, vec, Ray, Vec3};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-/// Constant null Material used as a default
-const NUL: geometry::Material = geometry::Material {
-    color: Vec3 {
-        x: 0.0,
-        y: 0.0,
-        z: 0.0,
-    },
-    t: geometry::MaterialType::Matte,
-};
+/// How many times smaller a preview is rendered on each side
+const PREVIEW_SCALE: u32 = 8;
 
-/// Returns a ray pointing at the image frame through a given pixel
-/// # Arguements
-/// * 'x' - A float for the x pixel
-/// * 'y' - A float for the y pixel
-/// * 'starting_pos' - A coordinate in 3 space for where the ray should emmenate from. Usually where the camera is
-/// * 'pixel_width' - The width in arbitrary units of a given pixel in our final image
-fn get_ray(x: f32, y: f32, starting_pos: Vec3, pixel_width: f32) -> Ray {
-    let img_x = (x * pixel_width) + (pixel_width / 2.0) - 1.0;
-    let img_y = -((y * pixel_width) + (pixel_width / 2.0) - 1.0);
-    let direction: Vec3 = norm(vec(img_x, img_y, -2.0));
-    return Ray {
-        start_pos: starting_pos,
-        direction_vector: direction,
-    };
-}
+/// Most reflections a preview follows
+const PREVIEW_REFLECTIONS: i32 = 2;
 
-/// Finds the closest surface to a ray's origin along its direction. Used to see what a Ray would hit first
-/// # Arguements
-/// * 'ray' - The ray we want to test
-/// * 'id' - An id of objects to ignore. Used to stop shadow/reflection acne
-/// * 'spheres' - a slice of spheres to check the ray against
-/// * 'triangles' - a slice of triangles to check the ray against
-fn find_closest_hit(ray: Ray, id: i8, spheres: &[Sphere], triangles: &[Triangle]) -> RayHit {
-    let mut r: RayHit = RayHit {
-        t: f32::MAX,
-        mat: NUL,
-        intersect: ray.start_pos,
-        surface_normal: ray.start_pos,
-        id: -2, // -2 is to flag as no-hit, should not come up
-    };
-
-    for sphere in spheres {
-        let temp = sphere_hit(*sphere, ray);
-        if (temp.t < r.t && temp.t > 0.0) && temp.id != id {
-            r = temp;
-        }
-    }
-
-    for triangle in triangles {
-        let temp = triangle_hit(*triangle, ray, r);
-        if (temp.t < r.t && temp.t > 0.0) && temp.id != id {
-            r = temp;
-        }
-    }
-
-    return r;
-}
+/// How often --watch looks at the scene file
+const WATCH_POLL: Duration = Duration::from_millis(250);
 
-fn diffuse_calc(r: RayHit, light: Vec3, spheres: &[Sphere], triangles: &[Triangle]) -> f32 {
-    let to_light = light - r.intersect;
-    let to_light_norm = norm(to_light);
-    let light_blocker = find_closest_hit(
-        Ray {
-            start_pos: r.intersect,
-            direction_vector: to_light_norm,
-        },
-        r.id,
-        &spheres,
-        &triangles,
-    );
-
-    if light_blocker.t > 0.0 && mag(&to_light) > light_blocker.t {
-        return 0.2;
-    }
-
-    return f32::clamp(to_light_norm * r.surface_normal, 0.2, 1.0); // TODO: 0.2 can be a shadow
-}
-
-fn specular_calc(
-    surface_norm: Vec3,
-    light_pos: Vec3,
-    pos: Vec3,
-    spheres: &[Sphere],
-    triangles: &[Triangle],
-    id: i8,
-) -> f32 {
-    // normalized vector from point to light
-    let light_dir_norm = norm(light_pos - pos);
-
-    // reflection of light vector across surface normal vector
-    let reflect = surface_norm * (surface_norm * light_dir_norm * 2.0) - light_dir_norm;
-
-    // basically how close that reflection is to our camera
-    let specular = (norm(reflect) * norm(pos * -1.0)).powf(11.0);
-
-    // make sure the light isn't getting blocked
-    let light_blocker = find_closest_hit(
-        Ray {
-            start_pos: pos,
-            direction_vector: light_dir_norm,
-        },
-        id,
-        &spheres,
-        &triangles,
-    );
-
-    if light_blocker.t > 0.0 && mag(&(light_pos - pos)) > light_blocker.t {
-        return 0.0;
-    }
-
-    // clamp values to the reasonable
-    return specular.clamp(0.0, 1.0);
-}
-
-fn read_lines(filename: String) -> io::Lines<BufReader<File>> {
-    // Open the file in read-only mode.
-    let file = File::open(filename).unwrap();
-    // Read the file line by line, and return an iterator of the lines of the file.
-    return io::BufReader::new(file).lines();
-}
-
-fn parse_vec(string: &str) -> Vec3 {
-    let mut chars = string.chars();
-    chars.next();
-    chars.next_back();
-    let fixed_str = chars.as_str();
-    let mut split = fixed_str.split(" ");
-    let x = split
-        .next()
-        .unwrap_or_else(|| -> &str { "" })
-        .parse::<f32>()
-        .unwrap_or_else(|_val| -> f32 { 0.0 });
-    let y = split
-        .next()
-        .unwrap_or_else(|| -> &str { "" })
-        .parse::<f32>()
-        .unwrap_or_else(|_val| -> f32 { 0.0 });
-    let z = split
-        .next()
-        .unwrap_or_else(|| -> &str { "" })
-        .parse::<f32>()
-        .unwrap_or_else(|_val| -> f32 { 0.0 });
-
-    return vec(x, y, z);
-}
+/// How long the scene file has to stay the same after changing before it's read again
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
 
 fn main() {
     // grab our args and spit out the executable name - we don't need it
@@ -163,181 +34,674 @@ fn main() {
     args.pop_front();
 
     // define some defauls
-    let mut pixel_count = 512 as u32;
-    let mut reflection_depth = 10;
-    let file_name = "./test.ray";
-    let mut lines = read_lines(file_name.to_string());
+    let mut width: u32 = 512;
+    let mut height: u32 = 512;
+    let mut settings = RenderSettings::default();
+    let mut file_name = "./test.ray".to_string();
+    let mut output = "test.png".to_string();
+    // 0 leaves it to rayon, which uses every core
+    let mut threads: usize = 0;
+    let mut stats_file: Option<String> = None;
+    let mut preview = false;
+    let mut watch = false;
+    let mut ppm_ascii = false;
+    let mut aovs = Aovs::default();
+    let mut alpha = false;
+    let mut frames = Frames::default();
+    // milliseconds each frame of an animated GIF is shown for
+    let mut gif_delay: u32 = 40;
+    let mut with_metadata = true;
 
     // loop over our args to check and see what command line args we have
-    for arg in args {
+    while let Some(arg) = args.pop_front() {
         // split the arguement into command and value - what we are configing and the value we are giving it
-        let mut split = arg.split("=");
-        let command = split.next().unwrap_or_else(|| -> &str { "none" });
-        let value = split.next().unwrap_or_else(|| -> &str { "" });
+        let mut split = arg.split('=');
+        let command = split.next().unwrap_or("none");
+        let value = split.next().unwrap_or_default();
 
         match command {
-            "--res" | "--resolution" => {
-                pixel_count = value
-                    .parse::<u32>()
-                    .unwrap_or_else(|_val: ParseIntError| -> u32 { 512 })
-            }
+            "--res" | "--resolution" => match parse_resolution(value) {
+                Some(size) => (width, height) = size,
+                None => println!("Invalid resolution {:?}, expected N or WxH", value),
+            },
             "--ref" | "--reflections" => {
-                reflection_depth = value
-                    .parse::<i32>()
-                    .unwrap_or_else(|_val: ParseIntError| -> i32 { 10 })
+                settings.reflection_depth = value.parse::<i32>().unwrap_or(10)
             }
             "--file" | "--input" | "--f" => {
-                lines = read_lines(value.to_string());
+                file_name = value.to_string();
+            }
+            // -o can also take the path as the next arguement, like most tools
+            "--output" | "-o" => {
+                let path = match value {
+                    "" if command == "-o" => args.pop_front().unwrap_or_default(),
+                    _ => value.to_string(),
+                };
+                match path.as_str() {
+                    "" => println!("Invalid output \"\", expected a path like out.png"),
+                    _ => output = path,
+                }
+            }
+            "--samples" => settings.samples = value.parse::<u32>().unwrap_or(1),
+            "--adaptive-threshold" => match value.parse::<f32>() {
+                Ok(threshold) if threshold >= 0.0 => settings.adaptive_threshold = Some(threshold),
+                _ => println!(
+                    "Invalid adaptive threshold {:?}, expected a color difference of 0 or more",
+                    value
+                ),
+            },
+            "--glossy-samples" => {
+                settings.glossy_samples = value.parse::<u32>().unwrap_or(8).max(1)
+            }
+            "--clamp-radiance" => match value.parse::<f32>() {
+                Ok(limit) if limit > 0.0 => settings.clamp_radiance = Some(limit),
+                _ => println!(
+                    "Invalid radiance clamp {:?}, expected a brightness above 0. Lower removes more bright \
+                     speckles but darkens bright lighting more",
+                    value
+                ),
+            },
+            "--denoise" => settings.denoise = true,
+            "--tile-size" => match value.parse::<u32>() {
+                Ok(size) if size > 0 => settings.tile_size = size,
+                _ => println!("Invalid tile size {:?}, expected pixels", value),
+            },
+            "--threads" => match value.parse::<usize>() {
+                Ok(count) if count > 0 => threads = count,
+                _ => println!("Invalid threads {:?}, expected a count of 1 or more", value),
+            },
+            "--progressive" => settings.progressive = true,
+            "--quiet" => settings.quiet = true,
+            "--preview" => preview = true,
+            "--watch" => watch = true,
+            "--ppm-ascii" => ppm_ascii = true,
+            "--alpha" => alpha = true,
+            "--no-metadata" => with_metadata = false,
+            "--frames" => match value.parse::<u32>() {
+                Ok(count) if count > 0 => frames.count = count,
+                _ => println!("Invalid frames {:?}, expected a count of 1 or more", value),
+            },
+            "--frame-start" => match value.parse::<u32>() {
+                Ok(number) => frames.start = number,
+                _ => println!("Invalid frame start {:?}, expected a frame number", value),
+            },
+            "--frame-padding" => match value.parse::<usize>() {
+                Ok(digits) => frames.padding = digits,
+                _ => println!("Invalid frame padding {:?}, expected a number of digits", value),
+            },
+            "--gif-delay" => match value.parse::<u32>() {
+                Ok(delay) => gif_delay = delay,
+                _ => println!("Invalid gif delay {:?}, expected milliseconds", value),
+            },
+            "--aov" => match value.split_once(':') {
+                Some(("depth", path)) if !path.is_empty() => {
+                    aovs.depth = Some(path.to_string())
+                }
+                Some(("normal", path)) if !path.is_empty() => {
+                    aovs.normal = Some(path.to_string())
+                }
+                Some(("id", path)) if !path.is_empty() => aovs.id = Some(path.to_string()),
+                _ => println!(
+                    "Invalid aov {:?}, expected depth:<path>, normal:<path>, or id:<path>",
+                    value
+                ),
+            },
+            "--depth-range" => match parse_depth_range(value) {
+                Some(range) => aovs.depth_range = Some(range),
+                None => println!(
+                    "Invalid depth range {:?}, expected near,far distances with far past near",
+                    value
+                ),
+            },
+            "--depth-scale" => match value {
+                "linear" => aovs.depth_scale = DepthScale::Linear,
+                "log" => aovs.depth_scale = DepthScale::Log,
+                _ => println!("Invalid depth scale {:?}, expected linear or log", value),
+            },
+            "--stats" => stats_file = Some(value.to_string()),
+            "--detailed-stats" => settings.detailed_stats = true,
+            "--save-interval" => match value.parse::<f32>() {
+                Ok(seconds) if seconds >= 0.0 => settings.save_interval = seconds,
+                _ => println!("Invalid save interval {:?}, expected seconds", value),
+            },
+            "--sampler" => match value {
+                "uniform" => settings.sampler = Sampler::Uniform,
+                "stratified" => settings.sampler = Sampler::Stratified,
+                _ => println!("Invalid sampler {:?}, expected uniform or stratified", value),
+            },
+            "--seed" => settings.seed = value.parse::<u64>().unwrap_or(0),
+            "--shadow-samples" => {
+                settings.shadow_samples = value.parse::<u32>().unwrap_or(16).max(1)
+            }
+            "--light" => match parse_point(value) {
+                Some(p) => settings.light_position = Some(p),
+                None => println!("Invalid light {:?}, expected x,y,z", value),
+            },
+            "--fov" => match value.parse::<f32>().ok().and_then(scene::valid_fov) {
+                Some(fov) => settings.fov = Some(fov),
+                None => println!(
+                    "Invalid fov {:?}, expected degrees between 0 and 180",
+                    value
+                ),
+            },
+            "--aperture" => settings.aperture = value.parse::<f32>().unwrap_or(0.0).max(0.0),
+            "--focal-dist" => {
+                settings.focal_distance = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|d| *d > 0.0)
+                    .unwrap_or(10.0)
+            }
+            "--mode" => match value {
+                "whitted" => settings.mode = Mode::Whitted,
+                "pathtrace" => settings.mode = Mode::PathTrace,
+                _ => println!("Invalid mode {:?}, expected whitted or pathtrace", value),
+            },
+            "--rr-depth" => match value.parse::<i32>() {
+                Ok(depth) if depth >= 0 => settings.rr_depth = depth,
+                _ => println!("Invalid rr depth {:?}, expected a bounce count of 0 or more", value),
+            },
+            "--projection" => match value.split_once(':').unwrap_or((value, "")) {
+                ("perspective", "") => settings.projection = Projection::Perspective,
+                ("equirect", "") => settings.projection = Projection::Equirect,
+                ("fisheye", fov_str) => {
+                    let fov = if fov_str.is_empty() {
+                        Some(180.0)
+                    } else {
+                        fov_str
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|f| *f > 0.0 && *f <= 360.0)
+                    };
+                    match fov {
+                        Some(fov) => settings.projection = Projection::Fisheye { fov },
+                        None => println!(
+                            "Invalid fisheye fov {:?}, expected degrees up to 360",
+                            fov_str
+                        ),
+                    }
+                }
+                _ => println!(
+                    "Invalid projection {:?}, expected perspective, fisheye:<fov>, or equirect",
+                    value
+                ),
+            },
+            "--roll" => match value.parse::<f32>() {
+                Ok(roll) if roll.is_finite() => settings.roll = Some(roll),
+                _ => println!("Invalid roll {:?}, expected degrees", value),
+            },
+            "--stereo" => match parse_stereo(value) {
+                Some(stereo) => settings.stereo = Some(stereo),
+                None => println!(
+                    "Invalid stereo {:?}, expected <separation> or <separation>:<convergence distance>",
+                    value
+                ),
+            },
+            "--camera" => match parse_point(value) {
+                Some(p) => settings.camera_position = Some(p),
+                None => println!("Invalid camera {:?}, expected x,y,z", value),
+            },
+            "--ao-samples" => settings.ao_samples = value.parse::<u32>().unwrap_or(0),
+            "--ao-radius" => {
+                settings.ao_radius = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|r| *r > 0.0)
+                    .unwrap_or(1.0)
             }
+            "--gamma" => {
+                settings.gamma = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|g| *g > 0.0)
+                    .unwrap_or(2.2)
+            }
+            "--exposure" => settings.exposure = value.parse::<f32>().unwrap_or(1.0),
+            "--tonemap" => match value {
+                "none" => settings.tonemap = Tonemap::None,
+                "reinhard" => settings.tonemap = Tonemap::Reinhard,
+                _ => println!("Invalid tonemap {:?}, expected none or reinhard", value),
+            },
+            "--cull" => settings.cull_backfaces = true,
+            "--no-bvh" => settings.accel = Accel::None,
+            "--accel" => match value {
+                "bvh" => settings.accel = Accel::Bvh,
+                "grid" => settings.accel = Accel::Grid,
+                "none" => settings.accel = Accel::None,
+                _ => println!("Invalid accel {:?}, expected bvh, grid, or none", value),
+            },
+            "--specular" => match value {
+                "phong" => settings.specular = SpecularModel::Phong,
+                "blinn" => settings.specular = SpecularModel::Blinn,
+                _ => println!("Invalid specular {:?}, expected phong or blinn", value),
+            },
             _ => println!("Invalid command: {:?}", command),
         }
     }
-    let mut spheres: Vec<Sphere> = Vec::new();
-    let mut triangles: Vec<Triangle> = Vec::new();
-
-    for line in lines {
-        let line_str = line.unwrap_or_default();
-        println!("{:?}", line_str);
-        let mut split = line_str.split(",");
-        match split.next().unwrap_or_default() {
-            "sphere" => {
-                let center_str = split.next().unwrap_or_default();
-                let rad_str = split.next().unwrap_or_default();
-                let color_str = split.next().unwrap_or_default();
-                let mat_type_str = split.next().unwrap_or_default();
-                let id_str = split.next().unwrap_or_default();
+    let extension = extension(&output);
+    // every frame goes into the one animated GIF, rather than a file each
+    let gif_output = extension == "gif";
+    if alpha && ["hdr", "ppm", "jpg", "jpeg", "gif"].contains(&extension.as_str()) {
+        println!(
+            ".{} can't keep partly see-through pixels, so --alpha is left off",
+            extension
+        );
+        alpha = false;
+    }
+    // rendered small and cheap, then blown back up so the file is still the size asked for
+    let (render_width, render_height) = if preview {
+        settings.reflection_depth = settings.reflection_depth.min(PREVIEW_REFLECTIONS);
+        settings.samples = 1;
+        let size = (
+            (width / PREVIEW_SCALE).max(1),
+            (height / PREVIEW_SCALE).max(1),
+        );
+        println!(
+            "*** PREVIEW: rendering at {}x{} with at most {} reflections and 1 sample, not final quality ***",
+            size.0, size.1, settings.reflection_depth
+        );
+        size
+    } else {
+        (width, height)
+    };
+    // stereo puts two images of the asked for width side by side
+    let eyes = if settings.stereo.is_some() { 2 } else { 1 };
+    let finish = |image: &image::Rgb32FImage| {
+        if !preview {
+            return image.clone();
+        }
+        return image::imageops::resize(
+            image,
+            width * eyes,
+            height,
+            image::imageops::FilterType::Nearest,
+        );
+    };
 
-                let center = parse_vec(center_str);
-                let color = parse_vec(color_str);
-                let radius = rad_str.parse::<f32>().unwrap_or_else(|_val| -> f32 { 0.0 });
-                let id = id_str.parse::<i8>().unwrap_or_else(|_val| -> i8 { -1 });
-                let mat_type = match mat_type_str {
-                    "matte" => geometry::MaterialType::Matte,
-                    "glossy" => geometry::MaterialType::Glossy,
-                    "refl" => geometry::MaterialType::Reflective,
-                    _ => geometry::MaterialType::Matte,
-                };
-                let sphere = Sphere {
-                    center,
-                    mat: geometry::Material {
-                        color: color,
-                        t: mat_type,
-                    },
-                    radius,
-                    id,
-                };
+    if let Err(e) = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+    {
+        println!("Could not start {} render threads: {}", threads, e);
+    }
 
-                spheres.push(sphere);
+    let run = || -> bool {
+        let mut timings = stats::Timings::default();
+        let start = Instant::now();
+        let mut scene = match Scene::from_ray_file(Path::new(&file_name), settings) {
+            Ok(scene) => scene,
+            Err(e) => {
+                println!("Could not load {}: {}", file_name, e);
+                return false;
             }
-            "triangle" => {
-                let a_str = split.next().unwrap_or_default();
-                let b_str = split.next().unwrap_or_default();
-                let c_str = split.next().unwrap_or_default();
-                let color_str = split.next().unwrap_or_default();
-                let mat_type_str = split.next().unwrap_or_default();
-                let id_str = split.next().unwrap_or_default();
-
-                let a = parse_vec(a_str);
-                let b = parse_vec(b_str);
-                let c = parse_vec(c_str);
-                let color = parse_vec(color_str);
-                let id = id_str.parse::<i8>().unwrap_or_else(|_val| -> i8 { -1 });
-                let mat_type = match mat_type_str {
-                    "matte" => geometry::MaterialType::Matte,
-                    "glossy" => geometry::MaterialType::Glossy,
-                    "refl" => geometry::MaterialType::Reflective,
-                    _ => geometry::MaterialType::Matte,
-                };
-                let triangle = Triangle {
-                    a,
-                    b,
-                    c,
-                    mat: geometry::Material { color, t: mat_type },
-                    id,
-                };
-
-                triangles.push(triangle);
+        };
+        timings.parsing = start.elapsed();
+
+        let mut gif = None;
+        if gif_output {
+            match gif::GifWriter::create(&output, gif_delay) {
+                Ok(writer) => gif = Some(writer),
+                Err(e) => {
+                    println!("Could not start {}: {}", output, e);
+                    return false;
+                }
             }
-            _ => println!("Invalid line"),
         }
-    }
-
-    let image_size = 2;
-    let pixel_width = image_size as f32 / pixel_count as f32;
-    let mut img: image::RgbImage = image::ImageBuffer::new(pixel_count, pixel_count);
 
-    let start_pos = vec(0.0, 0.0, 0.0);
-    let light_pos = vec(-3.0, 8.0, -6.0);
+        let mut pixels = 0;
+        for frame in 0..frames.count {
+            // the scene's only read once, with just what's animated moved along for each frame
+            let output = if gif_output {
+                output.clone()
+            } else {
+                frames.path(&output, frame)
+            };
+            if frames.count > 1 {
+                println!("Frame {} of {}: {}", frame + 1, frames.count, output);
+            }
+            let start = Instant::now();
+            scene.set_frame(frames.time(frame));
+            // how much each pixel covers, at the size the image is saved at
+            let mask = |render: &Render| {
+                return alpha.then(|| {
+                    let mask =
+                        aov::alpha_image(&render.coverage, render_width * eyes, render_height);
+                    return image::imageops::resize(
+                        &mask,
+                        width * eyes,
+                        height,
+                        image::imageops::FilterType::Nearest,
+                    );
+                });
+            };
+            // progressive renders save what they have so far to the same file, so it can be watched as it clears up
+            let render = scene.render(render_width, render_height, &mut |so_far| {
+                // a GIF only takes whole frames
+                if gif_output {
+                    return;
+                }
+                let saved = save(
+                    &scene,
+                    &finish(&so_far.image),
+                    mask(so_far).as_ref(),
+                    &output,
+                    ppm_ascii,
+                    None,
+                );
+                if let Err(e) = saved {
+                    println!("Could not save the image so far to {}: {}", output, e);
+                }
+            });
+            let image = &render.image;
+            let coverage = mask(&render);
+            // the extra images come from what each pixel's first camera ray hit
+            let surfaces = aovs.any().then_some(&render.surfaces);
+            let render_time = start.elapsed();
+            timings.rendering += render_time;
+
+            let start = Instant::now();
+            let finished = finish(image);
+            let metadata = with_metadata.then(|| {
+                metadata::describe(
+                    &scene,
+                    &file_name,
+                    finished.width(),
+                    finished.height(),
+                    render_time,
+                )
+            });
+            let saved = match gif.as_mut() {
+                Some(gif) => gif.add(&scene.develop(&finished)),
+                None => save(
+                    &scene,
+                    &finished,
+                    coverage.as_ref(),
+                    &output,
+                    ppm_ascii,
+                    metadata.as_ref(),
+                ),
+            };
+            if let Err(e) = saved {
+                println!("Could not save the image to {}: {}", output, e);
+                return false;
+            }
+            if let (Some(path), Some(surfaces)) = (&aovs.depth, &surfaces) {
+                let path = frames.path(path, frame);
+                let depth = aov::depth_image(
+                    surfaces,
+                    render_width * eyes,
+                    render_height,
+                    aovs.depth_range,
+                    aovs.depth_scale,
+                );
+                if let Err(e) = save_aov(depth.into(), &path, width * eyes, height) {
+                    println!("Could not save the depth to {}: {}", path, e);
+                    return false;
+                }
+            }
+            if let (Some(path), Some(surfaces)) = (&aovs.normal, &surfaces) {
+                let path = frames.path(path, frame);
+                let normal = aov::normal_image(surfaces, render_width * eyes, render_height);
+                if let Err(e) = save_aov(normal.into(), &path, width * eyes, height) {
+                    println!("Could not save the normals to {}: {}", path, e);
+                    return false;
+                }
+            }
+            if let (Some(path), Some(surfaces)) = (&aovs.id, &surfaces) {
+                let path = frames.path(path, frame);
+                let ids = aov::id_image(surfaces, render_width * eyes, render_height);
+                if let Err(e) = save_aov(ids.into(), &path, width * eyes, height) {
+                    println!("Could not save the object ids to {}: {}", path, e);
+                    return false;
+                }
+            }
+            timings.encoding += start.elapsed();
+            pixels += image.width() as u64 * image.height() as u64;
+        }
+        if let Some(gif) = gif {
+            if let Err(e) = gif.finish() {
+                println!("Could not finish {}: {}", output, e);
+                return false;
+            }
+        }
 
-    for (x, y, pixel) in img.enumerate_pixels_mut() {
-        let mut r = 0 as u8;
-        let mut g = 0 as u8;
-        let mut b = 0 as u8;
+        println!("{}", scene.world.stats.report(pixels, &timings));
+        if let Some(path) = &stats_file {
+            if let Err(e) = std::fs::write(path, scene.world.stats.to_json(pixels, &timings)) {
+                println!("Could not write stats to {}: {}", path, e);
+            }
+        }
 
-        let mut ray_to_target = get_ray(x as f32, y as f32, start_pos, pixel_width);
+        if preview {
+            println!("*** PREVIEW: {} is a low quality preview ***", output);
+        }
+        println!("Done!");
+        return true;
+    };
 
-        let mut ray_hit = find_closest_hit(ray_to_target, -1 as i8, &spheres, &triangles);
+    let rendered = run();
+    if !watch {
+        if !rendered {
+            std::process::exit(1);
+        }
+        return;
+    }
+    // polled rather than waiting on the OS to say, which works the same everywhere. Ctrl-C just ends the process,
+    // and images are saved whole or not at all, so it can't leave a broken one behind
+    println!("Watching {} for changes, Ctrl-C to stop", file_name);
+    let mut last = modified(&file_name);
+    loop {
+        thread::sleep(WATCH_POLL);
+        let mut now = modified(&file_name);
+        if now == last {
+            continue;
+        }
+        // editors often write a file in a few steps, so it's left until it stops changing
+        loop {
+            thread::sleep(WATCH_DEBOUNCE);
+            let again = modified(&file_name);
+            if again == now {
+                break;
+            }
+            now = again;
+        }
+        last = now;
+        println!("{} changed, rendering again", file_name);
+        run();
+    }
+}
 
-        if ray_hit.t >= 0.0 && ray_hit.t != f32::MAX {
-            if ray_hit.mat.t == geometry::MaterialType::Matte {
-                let diffuse = diffuse_calc(ray_hit, light_pos, &spheres, &triangles);
+/// When a file was last changed, or None if it can't be read right now, like partway through an editor replacing it
+fn modified(path: &str) -> Option<SystemTime> {
+    return std::fs::metadata(path).and_then(|m| m.modified()).ok();
+}
 
-                r = (ray_hit.mat.color.x * diffuse * 255.0) as u8;
-                g = (ray_hit.mat.color.y * diffuse * 255.0) as u8;
-                b = (ray_hit.mat.color.z * diffuse * 255.0) as u8;
-            } else if ray_hit.mat.t == geometry::MaterialType::Glossy {
-                let diffuse = diffuse_calc(ray_hit, light_pos, &spheres, &triangles);
-                let specular = specular_calc(
-                    ray_hit.surface_normal,
-                    light_pos,
-                    ray_hit.intersect,
-                    &spheres,
-                    &triangles,
-                    ray_hit.id,
-                );
+/// Saves a render in the format its extension calls for, like png, jpg, bmp, tiff, or ppm. .hdr and .exr keep the
+/// linear colors as they are, with no exposure, tone mapping, or gamma, so they can be graded elsewhere.
+/// # Arguements
+/// * 'scene' - what was rendered, which says how to develop it into a finished image
+/// * 'linear' - the render in linear light
+/// * 'alpha' - how see-through each pixel is, from 0 for the background up to 255, or None for an opaque image. Left
+///   out of .hdr and .ppm, which can't store it
+/// * 'ppm_ascii' - whether a .ppm is written as P3 text rather than binary P6
+/// * 'metadata' - how the render was made, written into .png files as text
+fn save(
+    scene: &Scene,
+    linear: &image::Rgb32FImage,
+    alpha: Option<&image::GrayImage>,
+    path: &str,
+    ppm_ascii: bool,
+    metadata: Option<&Metadata>,
+) -> image::ImageResult<()> {
+    let extension = extension(path);
+    return save_whole(path, |partial| {
+        match extension.as_str() {
+            "exr" => {
+                let linear = finite(linear);
+                match alpha {
+                    Some(alpha) => {
+                        let with_alpha = image::Rgba32FImage::from_fn(
+                            linear.width(),
+                            linear.height(),
+                            |x, y| {
+                                let image::Rgb([r, g, b]) = *linear.get_pixel(x, y);
+                                image::Rgba([r, g, b, alpha.get_pixel(x, y).0[0] as f32 / 255.0])
+                            },
+                        );
+                        with_alpha.save_with_format(partial, image::ImageFormat::OpenExr)?;
+                    }
+                    None => linear.save_with_format(partial, image::ImageFormat::OpenExr)?,
+                }
+            }
+            "hdr" => {
+                let linear = finite(linear);
+                let file = std::io::BufWriter::new(std::fs::File::create(partial)?);
+                let pixels: Vec<image::Rgb<f32>> = linear.pixels().copied().collect();
+                image::codecs::hdr::HdrEncoder::new(file).encode(
+                    &pixels,
+                    linear.width() as usize,
+                    linear.height() as usize,
+                )?;
+            }
+            "ppm" => ppm::write(&scene.develop(linear), Path::new(partial), ppm_ascii)?,
+            _ => {
+                let format = image::ImageFormat::from_path(path)?;
+                let image = scene.develop(linear);
+                let (image, color) = match alpha {
+                    Some(alpha) => {
+                        let with_alpha =
+                            image::RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+                                let image::Rgb([r, g, b]) = *image.get_pixel(x, y);
+                                image::Rgba([r, g, b, alpha.get_pixel(x, y).0[0]])
+                            });
+                        (
+                            image::DynamicImage::ImageRgba8(with_alpha),
+                            png::ColorType::Rgba,
+                        )
+                    }
+                    None => (image::DynamicImage::ImageRgb8(image), png::ColorType::Rgb),
+                };
+                match metadata {
+                    Some(metadata) if format == image::ImageFormat::Png => metadata::write_png(
+                        Path::new(partial),
+                        image.width(),
+                        image.height(),
+                        image.as_bytes(),
+                        color,
+                        metadata,
+                    )?,
+                    _ => image.save_with_format(partial, format)?,
+                }
+            }
+        }
+        return Ok(());
+    });
+}
 
-                r = ((ray_hit.mat.color.x * diffuse + specular) * 255.0) as u8;
-                g = ((ray_hit.mat.color.y * diffuse + specular) * 255.0) as u8;
-                b = ((ray_hit.mat.color.z * diffuse + specular) * 255.0) as u8;
-            } else {
-                let mut hit_space = false;
+/// Saves one of the extra images, blown back up to the size of the image when it's a preview
+fn save_aov(
+    aov: image::DynamicImage,
+    path: &str,
+    width: u32,
+    height: u32,
+) -> image::ImageResult<()> {
+    let aov = aov.resize_exact(width, height, image::imageops::FilterType::Nearest);
+    return save_whole(path, |partial| {
+        aov.save_with_format(partial, image::ImageFormat::from_path(path)?)
+    });
+}
 
-                for _i in 0..reflection_depth {
-                    if ray_hit.mat.t != geometry::MaterialType::Reflective {
-                        break;
-                    }
+/// A path's extension in lower case, or nothing if it hasn't got one
+fn extension(path: &str) -> String {
+    return Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+}
 
-                    let direction = norm(
-                        ray_hit.surface_normal
-                            * (-2.0 * (ray_to_target.direction_vector * ray_hit.surface_normal))
-                            + ray_to_target.direction_vector,
-                    );
+/// Writes a file with `write`, making any folders it goes in that don't exist yet. It's written alongside first and
+/// then moved into place, so anything reading the file, or stopping us partway, never sees half an image.
+/// # Arguements
+/// * 'path' - where the file ends up
+/// * 'write' - writes the file to the path it's given
+fn save_whole(
+    path: &str,
+    write: impl FnOnce(&str) -> image::ImageResult<()>,
+) -> image::ImageResult<()> {
+    if let Some(folder) = Path::new(path).parent() {
+        std::fs::create_dir_all(folder)?;
+    }
+    let partial = format!("{}.partial", path);
+    write(&partial)?;
+    std::fs::rename(&partial, path)?;
+    return Ok(());
+}
 
-                    ray_to_target = Ray {
-                        start_pos: ray_hit.intersect,
-                        direction_vector: direction,
-                    };
+/// A copy of a render with any pixels that aren't finite numbers made black, since float formats would keep the NaN or
+/// infinity and break whatever opens them. Says how many there were, if any.
+fn finite(linear: &image::Rgb32FImage) -> image::Rgb32FImage {
+    let mut flushed = linear.clone();
+    let mut count = 0;
+    for pixel in flushed.pixels_mut() {
+        if pixel.0.iter().any(|c| !c.is_finite()) {
+            *pixel = image::Rgb([0.0, 0.0, 0.0]);
+            count += 1;
+        }
+    }
+    if count > 0 {
+        println!(
+            "Warning: {} pixels weren't finite and were saved as black",
+            count
+        );
+    }
+    return flushed;
+}
 
-                    ray_hit = find_closest_hit(ray_to_target, ray_hit.id, &spheres, &triangles);
+/// Parses a point given on the command line as `x,y,z`
+fn parse_point(value: &str) -> Option<Vec3> {
+    let coords: Result<Vec<f32>, _> = value.split(',').map(|c| c.parse()).collect();
+    match coords.as_deref() {
+        Ok(&[x, y, z]) => return Some(vec(x, y, z)),
+        _ => return None,
+    }
+}
 
-                    if ray_hit.t < 0.0 || ray_hit.t == f32::MAX {
-                        hit_space = true;
-                        break;
-                    }
-                }
+/// Parses the distances a depth image runs between, given as `near,far`
+fn parse_depth_range(value: &str) -> Option<(f32, f32)> {
+    let (near, far) = value.split_once(',')?;
+    let (near, far) = (near.parse::<f32>().ok()?, far.parse::<f32>().ok()?);
+    if near < 0.0 || far <= near {
+        return None;
+    }
+    return Some((near, far));
+}
 
-                if ray_hit.mat.t != geometry::MaterialType::Reflective && !hit_space {
-                    let diffuse = diffuse_calc(ray_hit, light_pos, &spheres, &triangles);
-                    r = (ray_hit.mat.color.x * diffuse * 255.0) as u8;
-                    g = (ray_hit.mat.color.y * diffuse * 255.0) as u8;
-                    b = (ray_hit.mat.color.z * diffuse * 255.0) as u8;
-                }
-            }
+/// Parses a resolution given as `WxH`, or a single number for a square image. Both sides have to be at least 1.
+fn parse_resolution(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = match value.split_once('x') {
+        Some((w, h)) => (w.parse::<u32>().ok()?, h.parse::<u32>().ok()?),
+        None => {
+            let size = value.parse::<u32>().ok()?;
+            (size, size)
         }
-        *pixel = image::Rgb([r, g, b]);
+    };
+    if width == 0 || height == 0 {
+        return None;
     }
+    return Some((width, height));
+}
 
-    img.save("test.png").unwrap();
-
-    println!("Done!");
+/// Parses `<separation>` or `<separation>:<convergence distance>`, both positive
+fn parse_stereo(value: &str) -> Option<Stereo> {
+    let (separation_str, convergence_str) = value.split_once(':').unwrap_or((value, ""));
+    let separation = separation_str.parse::<f32>().ok().filter(|s| *s > 0.0)?;
+    let convergence = if convergence_str.is_empty() {
+        None
+    } else {
+        Some(convergence_str.parse::<f32>().ok().filter(|d| *d > 0.0)?)
+    };
+    return Some(Stereo {
+        separation,
+        convergence,
+    });
 }
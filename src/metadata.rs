@@ -0,0 +1,121 @@
+// Notes on how a render was made, written into its PNG as text so the settings behind an old image can be found
+// again. Most image viewers show them, and so does `exiftool`.
+
+use crate::scene::Scene;
+use crate::vec_math::Vec3;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::time::Duration;
+
+/// Names and values describing a render, in the order they're written
+pub type Metadata = Vec<(String, String)>;
+
+/// What went into a render
+/// # Arguements
+/// * 'scene' - what was rendered, and the settings it was rendered with
+/// * 'scene_file' - the file the scene was loaded from
+/// * 'width' - the saved image's width in pixels
+/// * 'height' - the saved image's height in pixels
+/// * 'render_time' - how long it took to render
+pub fn describe(
+    scene: &Scene,
+    scene_file: &str,
+    width: u32,
+    height: u32,
+    render_time: Duration,
+) -> Metadata {
+    let settings = &scene.settings;
+    let light = match scene.lights.first() {
+        Some(light) => match light.direction {
+            Some(direction) => format!("towards {}", point(direction)),
+            None => point(light.position),
+        },
+        None => "none".to_string(),
+    };
+    let camera = &scene.camera;
+    return vec![
+        ("Software".to_string(), "rust-tracer".to_string()),
+        ("Scene".to_string(), scene_file.to_string()),
+        ("Resolution".to_string(), format!("{}x{}", width, height)),
+        (
+            "Reflections".to_string(),
+            settings.reflection_depth.to_string(),
+        ),
+        ("Samples".to_string(), settings.samples.to_string()),
+        ("Seed".to_string(), settings.seed.to_string()),
+        ("Light".to_string(), light),
+        ("Camera position".to_string(), point(camera.position)),
+        ("Camera forward".to_string(), point(camera.forward)),
+        ("Camera up".to_string(), point(camera.up)),
+        ("Camera fov".to_string(), camera.fov().to_string()),
+        (
+            "Render time".to_string(),
+            format!("{:.3}s", render_time.as_secs_f64()),
+        ),
+    ];
+}
+
+/// A point written the same way the command line takes them, as `x,y,z`
+fn point(p: Vec3) -> String {
+    return format!("{},{},{}", p.x, p.y, p.z);
+}
+
+/// Writes an 8 bit RGB or RGBA image as a PNG, with each piece of metadata as a UTF-8 text chunk
+/// # Arguements
+/// * 'data' - the pixels, row by row
+/// * 'color' - whether `data` is RGB or RGBA
+/// * 'metadata' - names and values to write alongside the image
+pub fn write_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    data: &[u8],
+    color: png::ColorType,
+    metadata: &Metadata,
+) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (name, value) in metadata {
+        encoder.add_itxt_chunk(name.clone(), value.clone())?;
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_chunks_are_read_back() {
+        let metadata: Metadata = vec![
+            ("Software".to_string(), "rust-tracer".to_string()),
+            ("Scene".to_string(), "scènes/demo.ray".to_string()),
+            ("Seed".to_string(), "42".to_string()),
+        ];
+        let (width, height) = (3, 2);
+        let data: Vec<u8> = (0..width * height * 3).map(|i| i as u8 * 10).collect();
+        let path =
+            std::env::temp_dir().join(format!("raytracer_metadata_{}.png", std::process::id()));
+        write_png(&path, width, height, &data, png::ColorType::Rgb, &metadata).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(pixels, data);
+        let written: Metadata = reader
+            .info()
+            .utf8_text
+            .iter()
+            .map(|chunk| (chunk.keyword.clone(), chunk.get_text().unwrap()))
+            .collect();
+        assert_eq!(written, metadata);
+    }
+}
@@ -0,0 +1,213 @@
+// Hash based 3D noise and the procedural patterns built on it. Every lattice point gets fixed pseudo-random
+// numbers and everything in between is smoothly blended, so the same position always gives the same answer.
+
+use crate::vec_math::{vec, Vec3};
+
+/// Step used to measure how fast the noise changes
+const GRADIENT_STEP: f32 = 1e-3;
+
+/// Octaves of noise summed up for marble's veins
+const TURBULENCE_OCTAVES: i32 = 4;
+
+/// Scrambled bits for a lattice point
+fn hash(x: i32, y: i32, z: i32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    return h;
+}
+
+/// Pseudo-random value from 0 to 1 for a lattice point
+fn lattice(x: i32, y: i32, z: i32) -> f32 {
+    return (hash(x, y, z) & 0x00ff_ffff) as f32 / 16_777_216.0;
+}
+
+/// Dot product of an offset with one of the 12 directions to the edges of a cube, picked by a hash.
+/// Same set as Ken Perlin's improved noise.
+fn grad(h: u32, x: f32, y: f32, z: f32) -> f32 {
+    match h % 12 {
+        0 => return x + y,
+        1 => return -x + y,
+        2 => return x - y,
+        3 => return -x - y,
+        4 => return x + z,
+        5 => return -x + z,
+        6 => return x - z,
+        7 => return -x - z,
+        8 => return y + z,
+        9 => return -y + z,
+        10 => return y - z,
+        _ => return -y - z,
+    }
+}
+
+/// Eases from 0 to 1 with flat ends, so the blend has no creases at the lattice lines
+fn fade(t: f32) -> f32 {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    return a + (b - a) * t;
+}
+
+/// Noise from 0 to 1, changing about once per unit
+pub fn value_noise(p: Vec3) -> f32 {
+    let (x0, y0, z0) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (u, v, w) = (fade(p.x - x0), fade(p.y - y0), fade(p.z - z0));
+    let (x, y, z) = (x0 as i32, y0 as i32, z0 as i32);
+
+    let corner = |dx: i32, dy: i32, dz: i32| lattice(x + dx, y + dy, z + dz);
+    let near = lerp(
+        lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+        lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+        v,
+    );
+    let far = lerp(
+        lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+        lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+        v,
+    );
+    return lerp(near, far, w);
+}
+
+/// Which way and how quickly `value_noise` rises at a point
+pub fn value_noise_gradient(p: Vec3) -> Vec3 {
+    let h = GRADIENT_STEP;
+    let dx = value_noise(p + vec(h, 0.0, 0.0)) - value_noise(p - vec(h, 0.0, 0.0));
+    let dy = value_noise(p + vec(0.0, h, 0.0)) - value_noise(p - vec(0.0, h, 0.0));
+    let dz = value_noise(p + vec(0.0, 0.0, h)) - value_noise(p - vec(0.0, 0.0, h));
+    return vec(dx, dy, dz) * (1.0 / (2.0 * h));
+}
+
+/// Gradient (Perlin) noise from -1 to 1, changing about once per unit. Unlike value noise it's 0 at every
+/// lattice point and has no blocky look.
+pub fn gradient_noise(p: Vec3) -> f32 {
+    let (x0, y0, z0) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (fx, fy, fz) = (p.x - x0, p.y - y0, p.z - z0);
+    let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+    let (x, y, z) = (x0 as i32, y0 as i32, z0 as i32);
+
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        grad(
+            hash(x + dx, y + dy, z + dz),
+            fx - dx as f32,
+            fy - dy as f32,
+            fz - dz as f32,
+        )
+    };
+    let near = lerp(
+        lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+        lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+        v,
+    );
+    let far = lerp(
+        lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+        lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+        v,
+    );
+    // it only strays past 1 by a hair, but callers are promised the range
+    return lerp(near, far, w).clamp(-1.0, 1.0);
+}
+
+/// Gradient noise at several scales, each finer one fainter. Folded to be positive, which gives creases.
+fn turbulence(p: Vec3) -> f32 {
+    let mut sum = 0.0;
+    let mut scale = 1.0;
+    for _ in 0..TURBULENCE_OCTAVES {
+        sum += gradient_noise(p * scale).abs() / scale;
+        scale *= 2.0;
+    }
+    return sum;
+}
+
+/// How far a point is between marble's two colors, from 0 to 1. Wavy veins running across x.
+/// # Arguements
+/// * 'p' - the point in world space
+/// * 'frequency' - how tightly the veins are packed. One vein every 2*pi / frequency units
+pub fn marble(p: Vec3, frequency: f32) -> f32 {
+    let phase = p.x * frequency + 4.0 * turbulence(p * frequency * 0.5);
+    return 0.5 + 0.5 * phase.sin();
+}
+
+/// How far a point is between wood's two colors, from 0 to 1. Wobbly rings around the y axis, sharp on one edge
+/// like growth rings.
+/// # Arguements
+/// * 'p' - the point in world space
+/// * 'frequency' - about how many rings there are per unit
+pub fn wood(p: Vec3, frequency: f32) -> f32 {
+    let radius = (p.x * p.x + p.z * p.z).sqrt();
+    let rings = radius * frequency + 0.6 * gradient_noise(p * frequency * 0.5);
+    return rings - rings.floor();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points scattered over a few hundred units, negative coordinates included. Each axis steps by a different
+    /// irrational fraction, so they spread out evenly without needing random numbers
+    fn points() -> Vec<Vec3> {
+        return (0..20_000)
+            .map(|i| {
+                let coordinate = |step: f64| ((i as f64 * step).fract() - 0.5) as f32 * 400.0;
+                vec(
+                    coordinate(0.754_877_666),
+                    coordinate(0.569_840_291),
+                    coordinate(0.430_159_709),
+                )
+            })
+            .collect();
+    }
+
+    #[test]
+    fn gradient_noise_is_bounded_and_deterministic() {
+        let mut lowest = f32::MAX;
+        let mut highest = f32::MIN;
+        for p in points() {
+            let n = gradient_noise(p);
+            assert!((-1.0..=1.0).contains(&n), "{} at {:?}", n, p);
+            assert_eq!(n.to_bits(), gradient_noise(p).to_bits());
+            lowest = lowest.min(n);
+            highest = highest.max(n);
+        }
+        // and it isn't stuck near zero
+        assert!(lowest < -0.5 && highest > 0.5, "{} to {}", lowest, highest);
+    }
+
+    #[test]
+    fn gradient_noise_is_zero_on_the_lattice() {
+        for p in [
+            vec(0.0, 0.0, 0.0),
+            vec(3.0, -7.0, 12.0),
+            vec(-1.0, 5.0, -9.0),
+        ] {
+            assert_eq!(gradient_noise(p), 0.0);
+        }
+    }
+
+    #[test]
+    fn value_noise_is_bounded_and_deterministic() {
+        for p in points() {
+            let n = value_noise(p);
+            assert!((0.0..=1.0).contains(&n), "{} at {:?}", n, p);
+            assert_eq!(n.to_bits(), value_noise(p).to_bits());
+        }
+    }
+
+    #[test]
+    fn marble_and_wood_stay_between_their_colors() {
+        for p in points() {
+            for frequency in [0.5, 4.0] {
+                let m = marble(p, frequency);
+                let w = wood(p, frequency);
+                assert!((0.0..=1.0).contains(&m), "marble {} at {:?}", m, p);
+                assert!((0.0..=1.0).contains(&w), "wood {} at {:?}", w, p);
+                assert_eq!(m.to_bits(), marble(p, frequency).to_bits());
+                assert_eq!(w.to_bits(), wood(p, frequency).to_bits());
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+// Writes images as plain PPM, which is simple enough to read by eye or diff with text tools. P6 stores the pixels as
+// bytes, P3 writes each channel out as a number.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Writes an image to `path` as a PPM
+/// # Arguements
+/// * 'image' - the finished image, already gamma encoded
+/// * 'ascii' - whether to write P3, with the numbers written out, rather than binary P6
+pub fn write(image: &image::RgbImage, path: &Path, ascii: bool) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let magic = if ascii { "P3" } else { "P6" };
+    writeln!(out, "{}\n{} {}\n255", magic, image.width(), image.height())?;
+    if !ascii {
+        out.write_all(image.as_raw())?;
+        return out.flush();
+    }
+    // one row of the image to a line, which keeps them short enough for most diff tools
+    for row in image.rows() {
+        let line: Vec<String> = row
+            .flat_map(|pixel| pixel.0)
+            .map(|channel| channel.to_string())
+            .collect();
+        writeln!(out, "{}", line.join(" "))?;
+    }
+    return out.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads back a PPM's width, height and pixel bytes. Only handles the header the way `write` lays it out
+    fn read(path: &Path) -> (u32, u32, Vec<u8>) {
+        let bytes = std::fs::read(path).unwrap();
+        // the magic number, width, height, and maxval, each ended by one whitespace byte
+        let mut fields = Vec::new();
+        let mut start = 0;
+        while fields.len() < 4 {
+            let end = start
+                + bytes[start..]
+                    .iter()
+                    .position(|b| b.is_ascii_whitespace())
+                    .unwrap();
+            fields.push(std::str::from_utf8(&bytes[start..end]).unwrap().to_string());
+            start = end + 1;
+        }
+        assert_eq!(fields[3], "255");
+        let width = fields[1].parse().unwrap();
+        let height = fields[2].parse().unwrap();
+        let data = match fields[0].as_str() {
+            "P6" => bytes[start..].to_vec(),
+            "P3" => std::str::from_utf8(&bytes[start..])
+                .unwrap()
+                .split_ascii_whitespace()
+                .map(|n| n.parse().unwrap())
+                .collect(),
+            magic => panic!("unexpected magic number {:?}", magic),
+        };
+        return (width, height, data);
+    }
+
+    fn round_trip(ascii: bool) {
+        // not square, so swapped width and height show
+        let image = image::RgbImage::from_fn(7, 3, |x, y| {
+            image::Rgb([(x * 36) as u8, (y * 120) as u8, (x * 7 + y * 31) as u8])
+        });
+        let path = std::env::temp_dir().join(format!(
+            "raytracer_ppm_{}_{}.ppm",
+            ascii,
+            std::process::id()
+        ));
+        write(&image, &path, ascii).unwrap();
+        let (width, height, data) = read(&path);
+        // and other readers agree
+        let decoded = image::open(&path).unwrap().into_rgb8();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(decoded, image);
+        assert_eq!((width, height), image.dimensions());
+        assert_eq!(&data, image.as_raw());
+    }
+
+    #[test]
+    fn p6_round_trip() {
+        round_trip(false);
+    }
+
+    #[test]
+    fn p3_round_trip() {
+        round_trip(true);
+    }
+}
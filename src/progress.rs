@@ -0,0 +1,123 @@
+// Shows how far along a render is on stderr, with how long it's taken and about how long is left. Shared between the
+// render threads, which each add on what they finish.
+
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Least time between redraws of the bar, so printing doesn't slow down a quick render
+const REDRAW_MILLIS: u64 = 100;
+
+#[derive(Debug)]
+pub struct Progress {
+    /// What's being done, shown before the percentage
+    label: &'static str,
+    total: usize,
+    done: AtomicUsize,
+    start: Instant,
+    /// When the bar was last drawn, in milliseconds since the start. Whichever thread moves it on does the drawing
+    last_draw: AtomicU64,
+    /// How many tenths of the way were last reported, when stderr isn't a terminal
+    last_tenth: AtomicUsize,
+    /// Whether stderr is a terminal the bar can be redrawn in place on. Otherwise a line is printed every 10%
+    terminal: bool,
+    quiet: bool,
+}
+
+impl Progress {
+    /// # Arguements
+    /// * 'label' - what's being done, like "Rendering"
+    /// * 'total' - how much work there is, in whatever units `advance` is given
+    /// * 'quiet' - print nothing at all
+    pub fn new(label: &'static str, total: usize, quiet: bool) -> Progress {
+        return Progress {
+            label,
+            total,
+            done: AtomicUsize::new(0),
+            start: Instant::now(),
+            last_draw: AtomicU64::new(0),
+            last_tenth: AtomicUsize::new(0),
+            terminal: std::io::stderr().is_terminal(),
+            quiet,
+        };
+    }
+
+    /// Counts some more of the work as finished, and shows it if it's been long enough since it last was
+    pub fn advance(&self, amount: usize) {
+        let done = self.done.fetch_add(amount, Ordering::Relaxed) + amount;
+        if self.quiet {
+            return;
+        }
+        let elapsed = self.start.elapsed();
+        if self.terminal {
+            let now = elapsed.as_millis() as u64;
+            let last = self.last_draw.load(Ordering::Relaxed);
+            if now < last + REDRAW_MILLIS
+                || self
+                    .last_draw
+                    .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_err()
+            {
+                return;
+            }
+            // padded so a shorter line covers all of a longer one before it
+            eprint!("\r{:<50}", self.line(done, elapsed));
+        } else {
+            let tenth = done * 10 / self.total.max(1);
+            let last = self.last_tenth.load(Ordering::Relaxed);
+            if tenth <= last
+                || self
+                    .last_tenth
+                    .compare_exchange(last, tenth, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_err()
+            {
+                return;
+            }
+            eprintln!("{}", self.line(done, elapsed));
+        }
+    }
+
+    /// Prints a message on its own line, out of the way of the bar, which is drawn again on the next `advance`
+    pub fn message(&self, message: &str) {
+        if self.terminal && !self.quiet {
+            eprint!("\r{:50}\r", "");
+        }
+        println!("{}", message);
+    }
+
+    /// Shows the work as all done, leaving the bar on its own line
+    pub fn finish(&self) {
+        if self.quiet {
+            return;
+        }
+        let line = self.line(self.total, self.start.elapsed());
+        if self.terminal {
+            eprintln!("\r{:<50}", line);
+        } else if self.last_tenth.load(Ordering::Relaxed) < 10 {
+            eprintln!("{}", line);
+        }
+    }
+
+    /// The bar's text for some amount done
+    fn line(&self, done: usize, elapsed: Duration) -> String {
+        let fraction = (done as f32 / self.total.max(1) as f32).min(1.0);
+        let remaining = if fraction > 0.0 {
+            clock(elapsed.as_secs_f32() * (1.0 - fraction) / fraction)
+        } else {
+            "?".to_string()
+        };
+        return format!(
+            "{} {:5.1}%, {} elapsed, about {} left",
+            self.label,
+            fraction * 100.0,
+            clock(elapsed.as_secs_f32()),
+            remaining
+        );
+    }
+}
+
+/// Seconds as minutes and seconds, like 2:05
+fn clock(seconds: f32) -> String {
+    let seconds = seconds.round() as u64;
+    return format!("{}:{:02}", seconds / 60, seconds % 60);
+}
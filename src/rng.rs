@@ -0,0 +1,93 @@
+// Small seedable random number generator (PCG32), so anything noisy comes out the same every render.
+
+use crate::vec_math::{vec, Vec3};
+
+/// One step of splitmix64. Every bit of the input affects about half the bits of the output, so it turns counters
+/// and coordinates into well spread seeds.
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    return z ^ (z >> 31);
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// A generator whose numbers depend only on `seed`. Nearby seeds give unrelated streams.
+    pub fn new(seed: u64) -> Rng {
+        // scrambled so seeds like 0, 1, 2 don't start off looking alike
+        return Rng {
+            state: splitmix64(seed),
+        };
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        return xorshifted.rotate_right(rot);
+    }
+
+    /// Uniform from 0 up to but not including 1
+    pub fn next_f32(&mut self) -> f32 {
+        return (self.next_u32() >> 8) as f32 / 16_777_216.0;
+    }
+
+    /// A random point inside a sphere of radius 1 around the origin
+    pub fn in_unit_sphere(&mut self) -> Vec3 {
+        loop {
+            let p = vec(
+                self.next_f32() * 2.0 - 1.0,
+                self.next_f32() * 2.0 - 1.0,
+                self.next_f32() * 2.0 - 1.0,
+            );
+            if p * p < 1.0 {
+                return p;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_f32_is_in_range_with_mean_one_half() {
+        let mut rng = Rng::new(7);
+        let n = 100_000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let x = rng.next_f32();
+            assert!((0.0..1.0).contains(&x), "{} out of range", x);
+            sum += x as f64;
+        }
+        let mean = sum / n as f64;
+        assert!((mean - 0.5).abs() < 0.01, "mean {}", mean);
+    }
+
+    #[test]
+    fn same_seed_same_stream() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u32(), b.next_u32());
+        }
+    }
+
+    #[test]
+    fn in_unit_sphere_is_inside() {
+        let mut rng = Rng::new(3);
+        for _ in 0..10_000 {
+            let p = rng.in_unit_sphere();
+            assert!(p * p < 1.0);
+        }
+    }
+}
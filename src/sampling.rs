@@ -0,0 +1,225 @@
+// Random directions for effects that average many rays, drawn from the seedable Rng so renders repeat exactly.
+
+use crate::rng::Rng;
+use crate::vec_math::{cross, norm, vec, Vec3};
+
+/// A random unit direction on the side of a surface that `normal` points to. Directions near the normal come up
+/// more often, in proportion to the cosine of the angle from it, which is how much light from each one counts.
+/// # Arguements
+/// * 'normal' - normalized direction the hemisphere is centered on
+/// * 'rng' - where the randomness comes from
+pub fn cosine_hemisphere(normal: Vec3, rng: &mut Rng) -> Vec3 {
+    // a uniform point on the unit disk, lifted straight up onto the hemisphere above it
+    let (x, y) = in_unit_disk(rng);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+
+    // any two directions at right angles to the normal will do, starting from whichever axis is least like it
+    let helper = if normal.x.abs() < 0.9 {
+        vec(1.0, 0.0, 0.0)
+    } else {
+        vec(0.0, 1.0, 0.0)
+    };
+    let tangent = norm(cross(helper, normal));
+    let bitangent = cross(normal, tangent);
+    return norm(tangent * x + bitangent * y + normal * z);
+}
+
+/// A direction within a cone around `axis`, with every direction in it as likely as any other
+/// # Arguements
+/// * 'axis' - normalized direction down the middle of the cone
+/// * 'max_angle' - angle from the axis to the cone's edge, in radians
+/// * 'u' - from 0 to 1, picks how far out from the axis
+/// * 'v' - from 0 to 1, picks which way round it
+pub fn in_cone(axis: Vec3, max_angle: f32, u: f32, v: f32) -> Vec3 {
+    // evenly spread over the cap of the unit sphere the cone cuts out
+    let cos_theta = 1.0 - u * (1.0 - max_angle.cos());
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * std::f32::consts::PI * v;
+
+    let helper = if axis.x.abs() < 0.9 {
+        vec(1.0, 0.0, 0.0)
+    } else {
+        vec(0.0, 1.0, 0.0)
+    };
+    let tangent = norm(cross(helper, axis));
+    let bitangent = cross(axis, tangent);
+    return norm(
+        tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + axis * cos_theta,
+    );
+}
+
+/// A uniformly random point in a circle of radius 1, as its x and y
+pub fn in_unit_disk(rng: &mut Rng) -> (f32, f32) {
+    // square rooted so points don't bunch up in the middle
+    let r = rng.next_f32().sqrt();
+    let theta = 2.0 * std::f32::consts::PI * rng.next_f32();
+    return (r * theta.cos(), r * theta.sin());
+}
+
+/// How the spots used to average over an area, like a pixel or an area light, are picked
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Sampler {
+    /// Each spot anywhere at random, so some bunch up and leave gaps
+    Uniform,
+    /// The area is cut into a grid with one spot somewhere in each cell, which evens them out without the
+    /// banding of a regular grid. Spots past the largest square count that fits are uniform
+    Stratified,
+}
+
+impl Sampler {
+    /// The spot for one of several samples, somewhere in the unit square from (0, 0) to (1, 1)
+    /// # Arguements
+    /// * 'index' - which sample this is, from 0
+    /// * 'count' - how many samples are being taken over the area
+    /// * 'rng' - where the randomness comes from
+    pub fn point(self, index: u32, count: u32, rng: &mut Rng) -> (f32, f32) {
+        let side = (count as f32).sqrt() as u32;
+        match self {
+            Sampler::Stratified if index < side * side => {
+                let cell_x = (index % side) as f32;
+                let cell_y = (index / side) as f32;
+                return (
+                    (cell_x + rng.next_f32()) / side as f32,
+                    (cell_y + rng.next_f32()) / side as f32,
+                );
+            }
+            _ => return (rng.next_f32(), rng.next_f32()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec_math::mag;
+
+    const N: usize = 100_000;
+
+    #[test]
+    fn cosine_hemisphere_is_above_with_cosine_mean() {
+        let mut rng = Rng::new(13);
+        let normal = norm(vec(1.0, 2.0, -0.5));
+        let mut sum_cos = 0.0;
+        for _ in 0..N {
+            let d = cosine_hemisphere(normal, &mut rng);
+            assert!((mag(&d) - 1.0).abs() < 1e-4);
+            let cos = d * normal;
+            assert!(cos >= -1e-6, "{:?} below the surface", d);
+            sum_cos += cos as f64;
+        }
+        // the mean of cos θ weighted by cos θ over the hemisphere is 2/3
+        assert!((sum_cos / N as f64 - 2.0 / 3.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn cosine_hemisphere_stays_on_its_side_of_any_normal() {
+        // along and around the axes, where the tangent picked switches
+        let mut rng = Rng::new(17);
+        for normal in [
+            vec(1.0, 0.0, 0.0),
+            vec(-1.0, 0.0, 0.0),
+            vec(0.0, 1.0, 0.0),
+            vec(0.0, 0.0, -1.0),
+            norm(vec(0.9, 0.1, 0.0)),
+            norm(vec(0.89, -0.3, 0.2)),
+        ] {
+            for _ in 0..10_000 {
+                let d = cosine_hemisphere(normal, &mut rng);
+                assert!((mag(&d) - 1.0).abs() < 1e-4);
+                assert!(d * normal >= -1e-6, "{:?} below {:?}", d, normal);
+            }
+        }
+    }
+
+    #[test]
+    fn in_unit_disk_is_inside_and_centered() {
+        let mut rng = Rng::new(11);
+        let (mut sum_x, mut sum_y, mut sum_r2) = (0.0, 0.0, 0.0);
+        for _ in 0..N {
+            let (x, y) = in_unit_disk(&mut rng);
+            assert!(
+                x * x + y * y <= 1.0 + 1e-6,
+                "({}, {}) outside the disk",
+                x,
+                y
+            );
+            sum_x += x as f64;
+            sum_y += y as f64;
+            sum_r2 += (x * x + y * y) as f64;
+        }
+        assert!((sum_x / N as f64).abs() < 0.01);
+        assert!((sum_y / N as f64).abs() < 0.01);
+        // uniform over the area, so half the points are further out than r² = 1/2
+        assert!((sum_r2 / N as f64 - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn stratified_fills_every_cell_once() {
+        let mut rng = Rng::new(23);
+        for side in [1, 2, 3, 4, 8] {
+            let count = side * side;
+            for _ in 0..100 {
+                let mut cells = vec![0; count as usize];
+                for index in 0..count {
+                    let (x, y) = Sampler::Stratified.point(index, count, &mut rng);
+                    assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+                    let cell = (y * side as f32) as u32 * side + (x * side as f32) as u32;
+                    cells[cell as usize] += 1;
+                }
+                assert!(cells.iter().all(|&n| n == 1), "{:?}", cells);
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_past_the_square_is_uniform() {
+        // 5 samples fit a 2 by 2 grid, and the fifth lands anywhere
+        let mut rng = Rng::new(29);
+        let mut cells = [0; 4];
+        for index in 0..4 {
+            let (x, y) = Sampler::Stratified.point(index, 5, &mut rng);
+            cells[(y * 2.0) as usize * 2 + (x * 2.0) as usize] += 1;
+        }
+        assert_eq!(cells, [1, 1, 1, 1]);
+        let (x, y) = Sampler::Stratified.point(4, 5, &mut rng);
+        assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+    }
+
+    #[test]
+    fn in_cone_stays_inside_the_cone() {
+        let mut rng = Rng::new(19);
+        for axis in [
+            vec(0.0, 0.0, 1.0),
+            vec(1.0, 0.0, 0.0),
+            norm(vec(-1.0, 2.0, 3.0)),
+        ] {
+            for max_angle in [0.0, 0.05, 0.5, std::f32::consts::FRAC_PI_2] {
+                let mut widest: f32 = 0.0;
+                for _ in 0..10_000 {
+                    let d = in_cone(axis, max_angle, rng.next_f32(), rng.next_f32());
+                    assert!((mag(&d) - 1.0).abs() < 1e-4);
+                    let angle = (d * axis).clamp(-1.0, 1.0).acos();
+                    assert!(angle <= max_angle + 1e-3, "{} past {}", angle, max_angle);
+                    widest = widest.max(angle);
+                }
+                // and reaches out to the edge
+                assert!(
+                    widest >= max_angle * 0.95,
+                    "only out to {} of {}",
+                    widest,
+                    max_angle
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn in_cone_edges_of_the_unit_square() {
+        let axis = vec(0.0, 1.0, 0.0);
+        // u of 0 is straight down the axis, and 1 is on the rim
+        let d = in_cone(axis, 0.3, 0.0, 0.7);
+        assert!((d * axis - 1.0).abs() < 1e-6);
+        let d = in_cone(axis, 0.3, 1.0, 0.7);
+        assert!(((d * axis).acos() - 0.3).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,3765 @@
+// A scene loaded from a .ray file: the objects, lights, camera, and settings, plus the code to render it.
+
+use crate::bvh::{build_bvh, bvh_any_hit, bvh_closest_hit};
+use crate::denoise::{denoise, Surface};
+use crate::geometry::{
+    self, ray_aabb_intersect, transform_triangle, Albedo, Cone, Csg, CsgOp, Cylinder, Disk,
+    Ellipsoid, Instance, Mesh, Plane, PreparedTriangle, RayHit, Solid, SpecularModel, Sphere,
+    SphereSoA, Torus, Triangle, World, CLEAR, DEFAULT_SHININESS, WHITE,
+};
+use crate::grid::{build_grid, grid_any_hit, grid_closest_hit};
+use crate::loaders;
+use crate::noise::value_noise_gradient;
+use crate::progress::Progress;
+use crate::rng::{splitmix64, Rng};
+pub use crate::sampling::Sampler;
+use crate::sampling::{cosine_hemisphere, in_cone, in_unit_disk};
+use crate::stats::Counter;
+use crate::tiles::render_tiles;
+use crate::vec_math::{cross, mag, mul_elem, norm, vec, Mat3, Mat4, Ray, Vec3};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// The light used when a scene doesn't have any. With the default ambient, a surface facing straight at it comes to
+/// full brightness, same as before ambient had its own setting.
+const DEFAULT_LIGHT: Light = Light {
+    position: Vec3 {
+        x: -3.0,
+        y: 8.0,
+        z: -6.0,
+    },
+    direction: None,
+    color: WHITE,
+    intensity: 0.8,
+    area: None,
+    samples: None,
+    attenuation: NO_FALLOFF,
+    range: None,
+};
+
+/// Distance attenuation that leaves a light equally bright everywhere, for lights that don't give their own
+const NO_FALLOFF: Vec3 = Vec3 {
+    x: 1.0,
+    y: 0.0,
+    z: 0.0,
+};
+
+/// Light that reaches every surface, shadowed or not, when a scene doesn't say
+const DEFAULT_AMBIENT: Vec3 = Vec3 {
+    x: 0.2,
+    y: 0.2,
+    z: 0.2,
+};
+
+/// Which structure speeds up finding what a ray hits
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Accel {
+    #[default]
+    Bvh,
+    Grid,
+    /// Test every object against every ray
+    None,
+}
+
+/// How colors brighter than the screen can show are squeezed into range before they're written
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Tonemap {
+    /// Anything over 1 is cut off at full brightness
+    #[default]
+    None,
+    /// `c / (1 + c)`, which never quite reaches full brightness so bright spots fade out instead of flattening
+    Reinhard,
+}
+
+/// How pixels are turned into directions out of the camera
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Projection {
+    /// Through a flat image frame, so straight lines stay straight
+    #[default]
+    Perspective,
+    /// Equidistant fisheye. Distance from the middle of the image is the angle from straight ahead, out to half of
+    /// `fov` degrees at the edge of a circle as tall as the image. Pixels outside it are black.
+    Fisheye { fov: f32 },
+    /// The whole sphere around the camera, longitude across and latitude down like an environment map. Straight
+    /// ahead is the middle of the image, and behind is split between its left and right edges, which meet up.
+    Equirect,
+}
+
+/// How light reaching a surface is worked out
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Mode {
+    /// Straight from the lights, plus mirror and glass bounces, with flat ambient light standing in for the rest
+    #[default]
+    Whitted,
+    /// As well as the lights, diffuse surfaces gather light bounced off everything around them, one random
+    /// direction at a time, so colors bleed onto their neighbours. Noisy without plenty of samples
+    PathTrace,
+}
+
+/// A camera and where an image's pixels are on its frame
+#[derive(Debug, Copy, Clone)]
+struct View<'a> {
+    camera: &'a Camera,
+    /// How wide a pixel is on the frame
+    pixel_width: f32,
+    /// The image's width over its height
+    aspect: f32,
+}
+
+/// What the rays through one pixel found
+#[derive(Debug, Copy, Clone)]
+struct Pixel {
+    /// Their average color, in linear light
+    color: Vec3,
+    /// How many of them hit something rather than the background, from 0 to 1
+    coverage: f32,
+    /// What the first of them hit, or None if it's the background
+    surface: Option<Surface>,
+}
+
+/// A rendered image, along with what the camera rays found through each of its pixels on the way
+#[derive(Debug, Clone)]
+pub struct Render {
+    /// The image in linear light, which `Scene::develop` turns into a finished one
+    pub image: image::Rgb32FImage,
+    /// How much of each pixel something covers rather than the background, from 0 to 1, row by row. Only what the
+    /// camera sees first counts, not the background seen in reflections
+    pub coverage: Vec<f32>,
+    /// What each pixel's first camera ray hit, row by row, or None for the background
+    pub surfaces: Vec<Option<Surface>>,
+}
+
+impl Render {
+    /// Puts pixels worked out row by row into a render
+    /// # Arguements
+    /// * 'pixels' - each pixel, row by row
+    /// * 'width' - the image's width in pixels
+    /// * 'height' - the image's height in pixels
+    fn new(pixels: &[Pixel], width: u32, height: u32) -> Render {
+        let colors: Vec<Vec3> = pixels.iter().map(|pixel| pixel.color).collect();
+        return Render {
+            image: linear_image(&colors, width, height),
+            coverage: pixels.iter().map(|pixel| pixel.coverage).collect(),
+            surfaces: pixels.iter().map(|pixel| pixel.surface).collect(),
+        };
+    }
+
+    /// A black render that covers nothing, standing in for an eye that hasn't been rendered yet
+    /// # Arguements
+    /// * 'width' - the image's width in pixels
+    /// * 'height' - the image's height in pixels
+    fn blank(width: u32, height: u32) -> Render {
+        let pixels = (width * height) as usize;
+        return Render {
+            image: image::ImageBuffer::new(width, height),
+            coverage: vec![0.0; pixels],
+            surfaces: vec![None; pixels],
+        };
+    }
+
+    /// Puts renders of the same size side by side, left to right
+    /// # Arguements
+    /// * 'renders' - the renders, at least one
+    fn side_by_side(renders: &[Render]) -> Render {
+        let (width, height) = renders[0].image.dimensions();
+        let mut image: image::Rgb32FImage =
+            image::ImageBuffer::new(width * renders.len() as u32, height);
+        let mut coverage = Vec::with_capacity(renders.len() * renders[0].coverage.len());
+        let mut surfaces = Vec::with_capacity(coverage.capacity());
+        for y in 0..height as usize {
+            let row = y * width as usize..(y + 1) * width as usize;
+            for render in renders {
+                coverage.extend_from_slice(&render.coverage[row.clone()]);
+                surfaces.extend_from_slice(&render.surfaces[row.clone()]);
+            }
+        }
+        for (i, render) in renders.iter().enumerate() {
+            image::imageops::replace(&mut image, &render.image, (i as u32 * width) as i64, 0);
+        }
+        return Render {
+            image,
+            coverage,
+            surfaces,
+        };
+    }
+}
+
+/// Two cameras side by side, one for each eye
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stereo {
+    /// How far apart the eyes are, along the camera's right
+    pub separation: f32,
+    /// How far ahead the eyes turn in to meet, or None to look straight ahead in parallel
+    pub convergence: Option<f32>,
+}
+
+/// Options that change how a scene is loaded and rendered rather than what's in it
+#[derive(Debug, Copy, Clone)]
+pub struct RenderSettings {
+    /// Most mirror bounces a ray takes before giving up
+    pub reflection_depth: i32,
+    /// Skip hits on the back of single sided triangles
+    pub cull_backfaces: bool,
+    pub accel: Accel,
+    /// Highlight model for glossy materials that don't pick their own
+    pub specular: SpecularModel,
+    /// Rays averaged for each pixel, spread over its area to smooth edges. Rough reflections and the lens are
+    /// sampled once per ray
+    pub samples: u32,
+    /// With this set, pixels get one ray first and only those differing from a neighbour by more than this in any
+    /// channel get all of `samples`
+    pub adaptive_threshold: Option<f32>,
+    /// Rays averaged for each blurred reflection off a glossy surface seen straight from the camera
+    pub glossy_samples: u32,
+    /// Brightest any one ray's color can be before it's averaged into its pixel, which gets rid of lone
+    /// overbright pixels where a random bounce found something very bright. Bright things also lose some of the
+    /// light they really add, so the image comes out darker than it should, and None leaves it right
+    pub clamp_radiance: Option<f32>,
+    /// Smooths the finished image where neighbouring pixels see the same surface, keeping the edges between things
+    pub denoise: bool,
+    /// How wide and tall the squares of pixels render threads take at a time are
+    pub tile_size: u32,
+    /// Renders the whole image a sample at a time, handing over the image so far as it goes
+    pub progressive: bool,
+    /// Seconds between handing over the image so far in a progressive render
+    pub save_interval: f32,
+    /// Leaves out the progress bar while rendering
+    pub quiet: bool,
+    /// Counts every test of a ray against an object in the render stats, which costs a little speed
+    pub detailed_stats: bool,
+    /// How samples are spread over pixels and area lights
+    pub sampler: Sampler,
+    /// Seed for everything random, so the same seed always gives the same image. 0 unless `--seed` says otherwise
+    pub seed: u64,
+    /// Gamma the image is encoded with, and color textures decoded with. 1 writes the linear values as they are
+    pub gamma: f32,
+    /// How colors over 1 are brought into range
+    pub tonemap: Tonemap,
+    /// Every color is multiplied by this before tone mapping
+    pub exposure: f32,
+    /// Shadow rays cast towards area lights that don't pick their own count
+    pub shadow_samples: u32,
+    /// Rays cast to find how boxed in each camera hit is, darkening its ambient light. 0 turns it off
+    pub ao_samples: u32,
+    /// How close something has to be to count as boxing a hit in
+    pub ao_radius: f32,
+    /// Moves the scene's first light here, or the default light if it has none, whatever the file says
+    pub light_position: Option<Vec3>,
+    /// Puts the camera here, whatever the file says
+    pub camera_position: Option<Vec3>,
+    /// Degrees the image spans from top to bottom, whatever the file says
+    pub fov: Option<f32>,
+    /// Degrees the camera is turned about its aim, whatever the file says
+    pub roll: Option<f32>,
+    /// Radius of the camera's lens. Anything not at the focal distance is blurred, more so the wider it is. 0 is a
+    /// pinhole, which keeps everything sharp
+    pub aperture: f32,
+    /// How far in front of the camera things are perfectly sharp when there's a lens
+    pub focal_distance: f32,
+    pub projection: Projection,
+    pub mode: Mode,
+    /// Bounces path tracing always follows before it starts cutting paths short at random
+    pub rr_depth: i32,
+    /// Renders a view for each eye side by side, doubling the image's width
+    pub stereo: Option<Stereo>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        return RenderSettings {
+            reflection_depth: 10,
+            cull_backfaces: false,
+            accel: Accel::Bvh,
+            specular: SpecularModel::Phong,
+            samples: 1,
+            adaptive_threshold: None,
+            glossy_samples: 8,
+            clamp_radiance: None,
+            denoise: false,
+            tile_size: 32,
+            progressive: false,
+            save_interval: 10.0,
+            quiet: false,
+            detailed_stats: false,
+            sampler: Sampler::Stratified,
+            seed: 0,
+            gamma: 2.2,
+            tonemap: Tonemap::None,
+            exposure: 1.0,
+            shadow_samples: 16,
+            ao_samples: 0,
+            ao_radius: 1.0,
+            light_position: None,
+            camera_position: None,
+            fov: None,
+            roll: None,
+            aperture: 0.0,
+            focal_distance: 10.0,
+            projection: Projection::Perspective,
+            mode: Mode::Whitted,
+            rr_depth: 3,
+            stereo: None,
+        };
+    }
+}
+
+/// A point light, a rectangular area light centered on `position`, or a sun shining from `direction`
+#[derive(Debug, Copy, Clone)]
+pub struct Light {
+    pub position: Vec3,
+    /// Normalized direction towards a light infinitely far away, like the sun, or None for a light at `position`
+    pub direction: Option<Vec3>,
+    /// Tints everything the light adds, diffuse and highlights alike
+    pub color: Vec3,
+    /// How much the light adds to a surface facing straight at it, as a multiple of its color
+    pub intensity: f32,
+    /// The two edges of an area light, or None for a point. Area lights cast soft shadows
+    pub area: Option<(Vec3, Vec3)>,
+    /// Shadow rays cast towards an area light, or None to use the render's count
+    pub samples: Option<u32>,
+    /// Constant, linear, and quadratic falloff. The light's strength at distance d is intensity / (x + y*d + z*d^2)
+    pub attenuation: Vec3,
+    /// Distance past which the light adds nothing and isn't shadow tested, or None to reach everywhere
+    pub range: Option<f32>,
+}
+
+impl Light {
+    /// How much the light adds to a surface facing straight at it from `distance` away, as a multiple of its color
+    fn strength(&self, distance: f32) -> f32 {
+        let a = self.attenuation;
+        let falloff = a.x + a.y * distance + a.z * distance * distance;
+        // eases down to nothing at the edge of the range, so there's no hard circle where the light stops
+        let window = match self.range {
+            Some(range) => (1.0 - (distance / range).powi(4)).max(0.0).powi(2),
+            None => 1.0,
+        };
+        return self.intensity * window / falloff.max(f32::EPSILON);
+    }
+
+    /// Whether the light can add anything at a point, which is worth checking before casting shadow rays to it
+    fn reaches(&self, p: Vec3) -> bool {
+        return self
+            .range
+            .is_none_or(|range| mag(&(self.target(p) - p)) < range);
+    }
+
+    /// Where light comes from as seen from a point. For a sun that's far enough along its direction that every
+    /// point sees it the same way, and shadow rays run parallel.
+    fn target(&self, p: Vec3) -> Vec3 {
+        match self.direction {
+            Some(direction) => return p + direction * SUN_DISTANCE,
+            None => return self.position,
+        }
+    }
+}
+
+/// How far away a sun is put. Far past anything in a scene, but not so far it loses precision next to it.
+const SUN_DISTANCE: f32 = 1e5;
+
+/// The sun's color at some height, from 0 on the horizon to 1 overhead. Orange when it's low, near white when high
+fn sun_color(height: f32) -> Vec3 {
+    let h = height.clamp(0.0, 1.0).sqrt();
+    return vec(1.0, 0.55, 0.3) * (1.0 - h) + vec(1.0, 0.95, 0.9) * h;
+}
+
+/// A clear sky with the sun in it: blue overhead, warm at the horizon, and glowing around the sun. A low sun makes
+/// the sky dimmer and warmer.
+/// # Arguements
+/// * 'direction' - normalized direction being looked along
+/// * 'sun' - normalized direction towards the sun
+fn sun_sky(direction: Vec3, sun: Vec3) -> Vec3 {
+    let height = sun.y.clamp(0.0, 1.0);
+    let zenith = vec(0.08, 0.1, 0.25) * (1.0 - height) + vec(0.25, 0.45, 0.85) * height;
+    let horizon = vec(0.9, 0.5, 0.25) * (1.0 - height) + vec(0.75, 0.8, 0.85) * height;
+
+    // square rooted so the horizon color stays in a band near the horizon
+    let up = direction.y.max(0.0).sqrt();
+    let sky = horizon * (1.0 - up) + zenith * up;
+    // below the horizon fades to a dull ground
+    let sky = if direction.y < 0.0 {
+        sky * (1.0 - (-direction.y).sqrt() * 0.7)
+    } else {
+        sky
+    };
+
+    // a wide haze around the sun plus the bright disk itself
+    let facing = (direction * sun).max(0.0);
+    let glow = facing.powi(8) * 0.5 + facing.powi(2048) * 8.0;
+    return sky + sun_color(height) * glow;
+}
+
+/// What rays that escape the scene see: an environment map if there is one, otherwise the sky around a sun if
+/// there's one of those, otherwise a blend from `bottom` straight down to `top` straight up
+#[derive(Debug, Copy, Clone)]
+pub struct Background {
+    pub top: Vec3,
+    pub bottom: Vec3,
+    /// Index of a latitude-longitude image wrapped around the whole scene. Its middle is straight ahead down -z
+    pub map: Option<usize>,
+    /// Direction towards a sun, which swaps the gradient for a sky lit by it
+    pub sun: Option<Vec3>,
+}
+
+impl Background {
+    /// The sky color seen looking along a normalized direction
+    /// # Arguements
+    /// * 'direction' - which way the ray escapes
+    /// * 'textures' - the images materials and the environment map refer to
+    fn along(&self, direction: Vec3, textures: &[image::Rgb32FImage]) -> Vec3 {
+        if let Some(image) = self.map.and_then(|index| textures.get(index)) {
+            // longitude from -pi behind the camera round to pi, and latitude from the top pole down. Both are clamped
+            // in nearest_pixel, so the seam and the poles land on the edge texels
+            let u = 0.5 + f32::atan2(direction.x, -direction.z) / (2.0 * std::f32::consts::PI);
+            let v = f32::acos(direction.y.clamp(-1.0, 1.0)) / std::f32::consts::PI;
+            let [r, g, b] = geometry::nearest_pixel(image, u, v).0;
+            return vec(r, g, b);
+        }
+        if let Some(sun) = self.sun {
+            return sun_sky(direction, sun);
+        }
+        let t = ((direction.y + 1.0) * 0.5).clamp(0.0, 1.0);
+        return self.bottom * (1.0 - t) + self.top * t;
+    }
+}
+
+/// A pinhole camera looking through a 2 unit tall image frame. Looks down -z with +y up unless aimed elsewhere.
+#[derive(Debug, Copy, Clone)]
+pub struct Camera {
+    pub position: Vec3,
+    /// Unit vector towards the right of the image
+    pub right: Vec3,
+    /// Unit vector towards the top of the image
+    pub up: Vec3,
+    /// Unit vector from the camera through the middle of the image
+    pub forward: Vec3,
+    /// How far in front of the camera the image frame is. The default of 2 gives a field of view of about 53 degrees
+    pub frame_distance: f32,
+}
+
+impl Camera {
+    /// A camera at `eye` aimed at `target`, turned so `up` points as near the top of the image as it can. None if
+    /// the target is where the camera is, or `up` runs straight along the aim.
+    fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Option<Camera> {
+        let aim = target - eye;
+        if mag(&aim) == 0.0 {
+            return None;
+        }
+        let forward = norm(aim);
+        let side = cross(forward, up);
+        if mag(&side) < 1e-6 {
+            return None;
+        }
+        let right = norm(side);
+        return Some(Camera {
+            position: eye,
+            right,
+            up: cross(right, forward),
+            forward,
+            frame_distance: DEFAULT_FRAME_DISTANCE,
+        });
+    }
+
+    /// Turns the camera `roll` degrees about its aim. Positive turns it anticlockwise, so the picture turns
+    /// clockwise.
+    fn with_roll(self, roll: f32) -> Camera {
+        // exact for quarter turns, so rolling 90 degrees gives exactly the picture turned on its side
+        let turns = roll.rem_euclid(360.0);
+        let (sin, cos) = if turns == 0.0 {
+            (0.0, 1.0)
+        } else if turns == 90.0 {
+            (1.0, 0.0)
+        } else if turns == 180.0 {
+            (0.0, -1.0)
+        } else if turns == 270.0 {
+            (-1.0, 0.0)
+        } else {
+            roll.to_radians().sin_cos()
+        };
+        return Camera {
+            right: self.right * cos + self.up * sin,
+            up: self.up * cos - self.right * sin,
+            ..self
+        };
+    }
+
+    /// How many degrees the image frame spans from its top edge to its bottom
+    pub fn fov(&self) -> f32 {
+        return (1.0 / self.frame_distance).atan().to_degrees() * 2.0;
+    }
+
+    /// Moves the image frame so it spans `fov` degrees from its top edge to its bottom
+    fn with_fov(self, fov: f32) -> Camera {
+        return Camera {
+            frame_distance: 1.0 / (fov.to_radians() * 0.5).tan(),
+            ..self
+        };
+    }
+}
+
+/// How far the image frame is in front of the camera when no field of view is given
+const DEFAULT_FRAME_DISTANCE: f32 = 2.0;
+
+/// Checks a field of view in degrees, which has to be wider than nothing and narrower than straight out sideways
+pub fn valid_fov(fov: f32) -> Option<f32> {
+    if fov > 0.0 && fov < 180.0 {
+        return Some(fov);
+    }
+    return None;
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    /// The scene file couldn't be read
+    Io(io::Error),
+    /// A line that can't just be skipped, like one using a material that was never defined
+    Parse { line: usize, reason: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => return write!(f, "could not read scene file: {}", e),
+            SceneError::Parse { line, reason } => return write!(f, "line {}: {}", line, reason),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(e: io::Error) -> Self {
+        return SceneError::Io(e);
+    }
+}
+
+#[derive(Debug)]
+pub struct Scene {
+    pub world: World,
+    /// Each light shades every surface and casts its own shadows
+    pub lights: Vec<Light>,
+    pub camera: Camera,
+    /// Color of rays that escape the scene
+    pub background: Background,
+    /// Light that reaches every surface, even in shadow
+    pub ambient: Vec3,
+    /// How much of the light still gets through to shadowed surfaces, from 0 to 1. Materials can pick their own
+    pub shadow_intensity: f32,
+    /// Images used by texture materials, in the order materials refer to them. Already decoded, so colors are linear
+    /// and normal maps hold directions
+    pub textures: Vec<image::Rgb32FImage>,
+    /// Whether anything moves while the shutter's open. Still scenes never spend random numbers picking when rays
+    /// are cast
+    pub moving: bool,
+    /// Spheres that move from one frame of an animation to the next
+    pub animations: Vec<SphereAnimation>,
+    pub settings: RenderSettings,
+}
+
+/// A sphere going in a straight line over the course of an animation
+#[derive(Debug, Copy, Clone)]
+pub struct SphereAnimation {
+    /// Which of the world's objects it is
+    pub index: usize,
+    /// The sphere as it is on the first frame
+    pub sphere: Sphere,
+    /// How far its middle moves by the last frame
+    pub travel: Vec3,
+    /// Which of the scene's lights it gives off, for glowing spheres
+    pub light: Option<usize>,
+}
+
+impl Scene {
+    /// Loads a .ray file. Lines that can't be understood are reported and skipped rather than failing the whole scene,
+    /// except for material names that are unknown or defined twice.
+    /// # Arguements
+    /// * 'path' - the scene file. Paths inside it are relative to its folder
+    /// * 'settings' - how to load and later render the scene
+    pub fn from_ray_file(path: &Path, settings: RenderSettings) -> Result<Scene, SceneError> {
+        let lines = read_lines(path)?;
+        let scene_dir = path.parent().unwrap_or(Path::new("."));
+        let cull_backfaces = settings.cull_backfaces;
+        let mut world = World::default();
+        world.stats.detailed = settings.detailed_stats;
+        // the scene's own triangles, kept apart until the end so define blocks can swap them out
+        let mut triangles: Vec<Triangle> = Vec::new();
+        let mut current_mesh: Option<Mesh> = None;
+        // each entry is the full transform at that depth, already composed with everything above it
+        let mut transforms: Vec<Mat4> = Vec::new();
+        // triangles from define blocks, shared by every instance of them
+        let mut definitions: HashMap<String, Arc<Vec<PreparedTriangle>>> = HashMap::new();
+        // the name being defined and the scene's own triangles, set aside until the block ends
+        let mut defining: Option<(String, Vec<Triangle>)> = None;
+        let mut textures = TextureCache::new(settings.gamma);
+        let mut ambient = DEFAULT_AMBIENT;
+        let mut shadow_intensity = 0.0;
+        let mut lights: Vec<Light> = Vec::new();
+        let mut camera_position = vec(0.0, 0.0, 0.0);
+        // where the camera is aimed, or None to look straight down -z from wherever it ends up
+        let mut camera_target: Option<Vec3> = None;
+        let mut camera_up = vec(0.0, 1.0, 0.0);
+        let mut camera_fov: Option<f32> = None;
+        let mut camera_roll: Option<f32> = None;
+        // lights given off by emissive spheres, kept apart so the light override and default only see real ones
+        let mut emitters: Vec<Light> = Vec::new();
+        // whether anything moves while the shutter's open
+        let mut moving = false;
+        // spheres that move between frames, with the light they give off counted among the emitters until the end
+        let mut animations: Vec<SphereAnimation> = Vec::new();
+        let mut background = Background {
+            top: CLEAR,
+            bottom: CLEAR,
+            map: None,
+            sun: None,
+        };
+        // materials named by material lines, for other lines to use as @name
+        let mut materials: HashMap<String, geometry::Material> = HashMap::new();
+
+        for (line_num, line) in lines.enumerate() {
+            let line_str = line.unwrap_or_default();
+            println!("{:?}", line_str);
+            let mut split = line_str.split(',');
+            let kind = split.next().unwrap_or_default();
+
+            // reads either a named material like `@brick`, or a color field and a material field. A texture that
+            // can't be loaded leaves the object black rather than dropping it, and a bad modifier is left off
+            let mut load_material = |fields: &mut std::str::Split<char>| {
+                let color_str = fields.next().unwrap_or_default();
+                if let Some(name) = color_str.strip_prefix('@') {
+                    return match materials.get(name) {
+                        Some(&mat) => Ok(mat),
+                        None => Err(SceneError::Parse {
+                            line: line_num + 1,
+                            reason: format!("unknown material {:?}", name),
+                        }),
+                    };
+                }
+                let mat_type_str = fields.next().unwrap_or_default();
+                let albedo = match parse_albedo(color_str, scene_dir, &mut textures) {
+                    Ok(albedo) => albedo,
+                    Err(reason) => {
+                        println!("Invalid texture on line {}: {}", line_num + 1, reason);
+                        Albedo::Solid(vec(0.0, 0.0, 0.0))
+                    }
+                };
+                let mut parts = mat_type_str.split('+');
+                let mut mat = parse_material(albedo, parts.next().unwrap_or_default());
+                // mirrors tint what they reflect by their color, so a black one reflects nothing at all
+                if let geometry::MaterialType::Reflective {
+                    tint: Albedo::Solid(tint),
+                    ..
+                } = mat.t
+                {
+                    if tint.x <= 0.0 && tint.y <= 0.0 && tint.z <= 0.0 {
+                        println!(
+                            "Mirror on line {} is black, so it reflects nothing. Use (1 1 1) for a plain mirror",
+                            line_num + 1
+                        );
+                    }
+                }
+                for modifier in parts {
+                    if let Err(reason) =
+                        apply_modifier(&mut mat, modifier, scene_dir, &mut textures)
+                    {
+                        println!(
+                            "Invalid material modifier on line {}: {}",
+                            line_num + 1,
+                            reason
+                        );
+                    }
+                }
+                return Ok(mat);
+            };
+
+            let untransformable = [
+                "cylinder",
+                "cone",
+                "disk",
+                "torus",
+                "ellipsoid",
+                "csg",
+                "ground",
+                "light",
+                "arealight",
+                "sun",
+                "camera",
+            ];
+            if !transforms.is_empty() && untransformable.contains(&kind) {
+                println!(
+                    "Transforms are not supported for {}, line {} is placed as written",
+                    kind,
+                    line_num + 1
+                );
+            }
+
+            let definable = [
+                "triangle",
+                "striangle",
+                "uvtriangle",
+                "quad",
+                "obj",
+                "stl",
+                "enddefine",
+                "material",
+            ];
+            if defining.is_some() && !definable.contains(&kind) && !kind.is_empty() {
+                println!(
+                    "Only triangles can be defined, {} on line {} is placed in the scene",
+                    kind,
+                    line_num + 1
+                );
+            }
+
+            match kind {
+                "sphere" | "sphere_moving" | "sphere_anim" => {
+                    let center_str = split.next().unwrap_or_default();
+                    let rad_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+                    let velocity_str = split.next().unwrap_or_default();
+
+                    let center = parse_vec(center_str);
+                    let radius = rad_str.parse::<f32>().unwrap_or(0.0);
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+                    // how far it goes while the shutter's open, or for an animated sphere where it ends up on the
+                    // last frame. That's carried as its velocity until it's placed, so it's transformed the same way
+                    let velocity = match kind {
+                        "sphere_moving" => parse_vec(velocity_str),
+                        "sphere_anim" => parse_vec(velocity_str) - center,
+                        _ => vec(0.0, 0.0, 0.0),
+                    };
+                    let animated = kind == "sphere_anim";
+                    moving = moving || (!animated && mag(&velocity) > 0.0);
+
+                    // a negative radius turns the sphere inside out
+                    let sphere = Sphere {
+                        center,
+                        velocity,
+                        mat,
+                        radius: radius.abs(),
+                        inverted: radius < 0.0,
+                        id,
+                    };
+
+                    let placed = match transforms.last() {
+                        Some(m) => match transform_sphere(sphere, m) {
+                            Ok(placed) => placed,
+                            Err(reason) => {
+                                println!("Invalid sphere on line {}: {}", line_num + 1, reason);
+                                continue;
+                            }
+                        },
+                        None => TransformedSphere::Sphere(sphere),
+                    };
+
+                    // glowing spheres light the rest of the scene from their centers once placed. Their own surface
+                    // lets the light straight through, so they don't shadow it
+                    let emissive = matches!(mat.t, geometry::MaterialType::Emissive { .. });
+                    if let geometry::MaterialType::Emissive { intensity, .. } = mat.t {
+                        let position = match placed {
+                            // set_frame moves the light along with the sphere from where it starts
+                            TransformedSphere::Sphere(sphere) if animated => sphere.center,
+                            // a light can't move while the shutter's open, so it stays where the sphere is halfway
+                            TransformedSphere::Sphere(sphere) => {
+                                sphere.center + sphere.velocity * 0.5
+                            }
+                            TransformedSphere::Ellipsoid(ellipsoid) => ellipsoid.center,
+                        };
+                        emitters.push(Light {
+                            position,
+                            direction: None,
+                            color: mat.albedo(position, 0.5, 0.5, &textures.images),
+                            intensity,
+                            area: None,
+                            samples: None,
+                            attenuation: NO_FALLOFF,
+                            range: None,
+                        });
+                    }
+
+                    match placed {
+                        TransformedSphere::Sphere(sphere) if animated => {
+                            let travel = sphere.velocity;
+                            let sphere = Sphere {
+                                velocity: vec(0.0, 0.0, 0.0),
+                                ..sphere
+                            };
+                            animations.push(SphereAnimation {
+                                index: world.objects.len(),
+                                sphere,
+                                travel,
+                                light: emissive.then(|| emitters.len() - 1),
+                            });
+                            world.add(sphere);
+                        }
+                        TransformedSphere::Sphere(sphere) => world.add(sphere),
+                        TransformedSphere::Ellipsoid(ellipsoid) => world.add(ellipsoid),
+                    }
+                }
+                "triangle" => {
+                    let a_str = split.next().unwrap_or_default();
+                    let b_str = split.next().unwrap_or_default();
+                    let c_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+                    let sides_str = split.next().unwrap_or_default();
+
+                    let a = parse_vec(a_str);
+                    let b = parse_vec(b_str);
+                    let c = parse_vec(c_str);
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+                    let triangle = Triangle {
+                        a,
+                        b,
+                        c,
+                        vertex_normals: None,
+                        uvs: None,
+                        double_sided: sides_str == "double",
+                        cull_backfaces,
+                        mat,
+                        id,
+                    };
+
+                    triangles.push(place_triangle(triangle, transforms.last(), cull_backfaces));
+                }
+                "striangle" => {
+                    let a_str = split.next().unwrap_or_default();
+                    let b_str = split.next().unwrap_or_default();
+                    let c_str = split.next().unwrap_or_default();
+                    let na_str = split.next().unwrap_or_default();
+                    let nb_str = split.next().unwrap_or_default();
+                    let nc_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+                    let sides_str = split.next().unwrap_or_default();
+
+                    let a = parse_vec(a_str);
+                    let b = parse_vec(b_str);
+                    let c = parse_vec(c_str);
+                    let normals = [
+                        norm(parse_vec(na_str)),
+                        norm(parse_vec(nb_str)),
+                        norm(parse_vec(nc_str)),
+                    ];
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+                    let triangle = Triangle {
+                        a,
+                        b,
+                        c,
+                        vertex_normals: Some(normals),
+                        uvs: None,
+                        double_sided: sides_str == "double",
+                        cull_backfaces,
+                        mat,
+                        id,
+                    };
+
+                    triangles.push(place_triangle(triangle, transforms.last(), cull_backfaces));
+                }
+                "uvtriangle" => {
+                    let a_str = split.next().unwrap_or_default();
+                    let b_str = split.next().unwrap_or_default();
+                    let c_str = split.next().unwrap_or_default();
+                    let uva_str = split.next().unwrap_or_default();
+                    let uvb_str = split.next().unwrap_or_default();
+                    let uvc_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+                    let sides_str = split.next().unwrap_or_default();
+
+                    let a = parse_vec(a_str);
+                    let b = parse_vec(b_str);
+                    let c = parse_vec(c_str);
+                    let uvs = [parse_uv(uva_str), parse_uv(uvb_str), parse_uv(uvc_str)];
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+                    let triangle = Triangle {
+                        a,
+                        b,
+                        c,
+                        vertex_normals: None,
+                        uvs: Some(uvs),
+                        double_sided: sides_str == "double",
+                        cull_backfaces,
+                        mat,
+                        id,
+                    };
+
+                    triangles.push(place_triangle(triangle, transforms.last(), cull_backfaces));
+                }
+                "plane" => {
+                    let point_str = split.next().unwrap_or_default();
+                    let normal_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+
+                    let point = parse_vec(point_str);
+                    let normal = norm(parse_vec(normal_str));
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+                    let plane = Plane {
+                        point,
+                        normal,
+                        mat,
+                        id,
+                    };
+
+                    world.add(match transforms.last() {
+                        Some(m) => Plane {
+                            point: m.transform_point(plane.point),
+                            normal: m.transform_normal(plane.normal),
+                            ..plane
+                        },
+                        None => plane,
+                    });
+                }
+                "cylinder" => {
+                    let base_str = split.next().unwrap_or_default();
+                    let axis_str = split.next().unwrap_or_default();
+                    let rad_str = split.next().unwrap_or_default();
+                    let height_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+
+                    let base = parse_vec(base_str);
+                    let axis = norm(parse_vec(axis_str));
+                    let radius = rad_str.parse::<f32>().unwrap_or(0.0);
+                    let height = height_str.parse::<f32>().unwrap_or(0.0);
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+                    let cylinder = Cylinder {
+                        base,
+                        axis,
+                        radius,
+                        height,
+                        mat,
+                        id,
+                    };
+
+                    world.add(cylinder);
+                }
+                "cone" => {
+                    let apex_str = split.next().unwrap_or_default();
+                    let axis_str = split.next().unwrap_or_default();
+                    let rad_str = split.next().unwrap_or_default();
+                    let height_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+                    let cap_str = split.next().unwrap_or_default();
+
+                    let apex = parse_vec(apex_str);
+                    let axis = norm(parse_vec(axis_str));
+                    let radius = rad_str.parse::<f32>().unwrap_or(0.0);
+                    let height = height_str.parse::<f32>().unwrap_or(0.0);
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+                    let cone = Cone {
+                        apex,
+                        axis,
+                        radius,
+                        height,
+                        capped: cap_str != "open",
+                        mat,
+                        id,
+                    };
+
+                    world.add(cone);
+                }
+                "disk" => {
+                    let center_str = split.next().unwrap_or_default();
+                    let normal_str = split.next().unwrap_or_default();
+                    let rad_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+
+                    let center = parse_vec(center_str);
+                    let normal = norm(parse_vec(normal_str));
+                    let radius = rad_str.parse::<f32>().unwrap_or(0.0);
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+                    let disk = Disk {
+                        center,
+                        normal,
+                        radius,
+                        mat,
+                        id,
+                    };
+
+                    world.add(disk);
+                }
+                "torus" => {
+                    let center_str = split.next().unwrap_or_default();
+                    let axis_str = split.next().unwrap_or_default();
+                    let major_str = split.next().unwrap_or_default();
+                    let minor_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+
+                    let center = parse_vec(center_str);
+                    let axis = norm(parse_vec(axis_str));
+                    let major_radius = major_str.parse::<f32>().unwrap_or(0.0);
+                    let minor_radius = minor_str.parse::<f32>().unwrap_or(0.0);
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+                    let torus = Torus {
+                        center,
+                        axis,
+                        major_radius,
+                        minor_radius,
+                        mat,
+                        id,
+                    };
+
+                    world.add(torus);
+                }
+                "ellipsoid" => {
+                    let center_str = split.next().unwrap_or_default();
+                    let radii_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+
+                    let center = parse_vec(center_str);
+                    let radii = parse_vec(radii_str);
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+                    let ellipsoid = Ellipsoid {
+                        center,
+                        radii,
+                        mat,
+                        id,
+                    };
+
+                    world.add(ellipsoid);
+                }
+                "quad" => {
+                    let a_str = split.next().unwrap_or_default();
+                    let b_str = split.next().unwrap_or_default();
+                    let c_str = split.next().unwrap_or_default();
+                    let d_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+                    let sides_str = split.next().unwrap_or_default();
+
+                    let a = parse_vec(a_str);
+                    let b = parse_vec(b_str);
+                    let c = parse_vec(c_str);
+                    let d = parse_vec(d_str);
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+
+                    match split_quad(a, b, c, d, mat, id, sides_str == "double") {
+                        Ok(halves) => triangles.extend(
+                            halves.map(|tr| place_triangle(tr, transforms.last(), cull_backfaces)),
+                        ),
+                        Err(reason) => {
+                            println!("Invalid quad on line {}: {}", line_num + 1, reason)
+                        }
+                    }
+                }
+                "obj" => {
+                    let path_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+                    let sides_str = split.next().unwrap_or_default();
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+
+                    // relative paths are relative to the scene file, not wherever we were run from
+                    match loaders::obj::load_obj(
+                        &scene_dir.join(path_str),
+                        mat,
+                        id,
+                        sides_str == "double",
+                    ) {
+                        Ok(loaded) => triangles.extend(
+                            loaded
+                                .into_iter()
+                                .map(|tr| place_triangle(tr, transforms.last(), cull_backfaces)),
+                        ),
+                        Err(reason) => println!("Invalid obj on line {}: {}", line_num + 1, reason),
+                    }
+                }
+                "stl" => {
+                    let path_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+                    let sides_str = split.next().unwrap_or_default();
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+
+                    match loaders::stl::load_stl(
+                        &scene_dir.join(path_str),
+                        mat,
+                        id,
+                        sides_str == "double",
+                    ) {
+                        Ok(loaded) => triangles.extend(
+                            loaded
+                                .into_iter()
+                                .map(|tr| place_triangle(tr, transforms.last(), cull_backfaces)),
+                        ),
+                        Err(reason) => println!("Invalid stl on line {}: {}", line_num + 1, reason),
+                    }
+                }
+                "csg" => {
+                    let op_str = split.next().unwrap_or_default();
+                    let left_str = split.next().unwrap_or_default();
+                    let right_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+
+                    let op = match op_str {
+                        "union" => CsgOp::Union,
+                        "intersection" => CsgOp::Intersection,
+                        "difference" => CsgOp::Difference,
+                        _ => {
+                            println!("Invalid csg operation on line {}", line_num + 1);
+                            continue;
+                        }
+                    };
+                    let (Some(left), Some(right)) = (parse_solid(left_str), parse_solid(right_str))
+                    else {
+                        println!("Invalid csg shape on line {}", line_num + 1);
+                        continue;
+                    };
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+                    let csg = Csg {
+                        op,
+                        left,
+                        right,
+                        mat,
+                        id,
+                    };
+
+                    world.add(csg);
+                }
+                "ground" => {
+                    let height_str = split.next().unwrap_or_default();
+                    let color_a_str = split.next().unwrap_or_default();
+                    let color_b_str = split.next().unwrap_or_default();
+                    let tile_str = split.next().unwrap_or_default();
+
+                    let height = height_str.parse::<f32>().unwrap_or(0.0);
+                    let a = parse_vec(color_a_str);
+                    let b = parse_vec(color_b_str);
+                    let tile = tile_str.parse::<f32>().unwrap_or(1.0);
+                    let plane = Plane {
+                        point: vec(0.0, height, 0.0),
+                        normal: vec(0.0, 1.0, 0.0),
+                        mat: geometry::Material::new(geometry::MaterialType::Matte {
+                            // squares on the x/z plane, with the ground half way up a layer of cubes
+                            albedo: Albedo::Checker(geometry::Checker {
+                                a,
+                                b,
+                                tile,
+                                origin: vec(0.0, height - tile / 2.0, 0.0),
+                            }),
+                        }),
+                        id: GROUND_ID,
+                    };
+
+                    world.add(plane);
+                }
+                "material" => {
+                    let name = split.next().unwrap_or_default().to_string();
+                    let mut mat = load_material(&mut split)?;
+                    if name.is_empty() {
+                        println!("Material without a name on line {}", line_num + 1);
+                        continue;
+                    }
+                    if materials.contains_key(&name) {
+                        return Err(SceneError::Parse {
+                            line: line_num + 1,
+                            reason: format!("material {:?} is already defined", name),
+                        });
+                    }
+                    // anything after the material field is a modifier, same as after a +
+                    for modifier in split {
+                        if let Err(reason) =
+                            apply_modifier(&mut mat, modifier, scene_dir, &mut textures)
+                        {
+                            println!(
+                                "Invalid material modifier on line {}: {}",
+                                line_num + 1,
+                                reason
+                            );
+                        }
+                    }
+                    materials.insert(name, mat);
+                }
+                "light" => {
+                    let position_str = split.next().unwrap_or_default();
+                    let color_str = split.next().unwrap_or_default();
+                    let intensity_str = split.next().unwrap_or_default();
+                    let attenuation_str = split.next().unwrap_or_default();
+                    let range_str = split.next().unwrap_or_default();
+
+                    // white and at full strength unless told otherwise
+                    let color = if color_str.is_empty() {
+                        WHITE
+                    } else {
+                        parse_vec(color_str)
+                    };
+                    lights.push(Light {
+                        position: parse_vec(position_str),
+                        direction: None,
+                        color,
+                        intensity: intensity_str.parse::<f32>().unwrap_or(1.0).max(0.0),
+                        area: None,
+                        samples: None,
+                        attenuation: parse_attenuation(attenuation_str),
+                        range: parse_range(range_str),
+                    });
+                }
+                "arealight" => {
+                    let center_str = split.next().unwrap_or_default();
+                    let u_str = split.next().unwrap_or_default();
+                    let v_str = split.next().unwrap_or_default();
+                    let color_str = split.next().unwrap_or_default();
+                    let intensity_str = split.next().unwrap_or_default();
+                    let samples_str = split.next().unwrap_or_default();
+                    let attenuation_str = split.next().unwrap_or_default();
+                    let range_str = split.next().unwrap_or_default();
+
+                    let color = if color_str.is_empty() {
+                        WHITE
+                    } else {
+                        parse_vec(color_str)
+                    };
+                    lights.push(Light {
+                        position: parse_vec(center_str),
+                        direction: None,
+                        color,
+                        intensity: intensity_str.parse::<f32>().unwrap_or(1.0).max(0.0),
+                        area: Some((parse_vec(u_str), parse_vec(v_str))),
+                        samples: samples_str.parse::<u32>().ok().filter(|&n| n > 0),
+                        attenuation: parse_attenuation(attenuation_str),
+                        range: parse_range(range_str),
+                    });
+                }
+                "sun" => {
+                    let azimuth_str = split.next().unwrap_or_default();
+                    let elevation_str = split.next().unwrap_or_default();
+                    let intensity_str = split.next().unwrap_or_default();
+
+                    // azimuth turns clockwise seen from above, starting straight ahead down -z
+                    let azimuth = azimuth_str.parse::<f32>().unwrap_or(0.0).to_radians();
+                    let elevation = elevation_str
+                        .parse::<f32>()
+                        .unwrap_or(45.0)
+                        .clamp(-90.0, 90.0)
+                        .to_radians();
+                    let direction = vec(
+                        elevation.cos() * azimuth.sin(),
+                        elevation.sin(),
+                        -elevation.cos() * azimuth.cos(),
+                    );
+                    lights.push(Light {
+                        position: direction * SUN_DISTANCE,
+                        direction: Some(direction),
+                        color: sun_color(direction.y),
+                        intensity: intensity_str.parse::<f32>().unwrap_or(1.0).max(0.0),
+                        area: None,
+                        samples: None,
+                        attenuation: NO_FALLOFF,
+                        range: None,
+                    });
+                    background.sun = Some(direction);
+                }
+                "camera" => {
+                    let eye_str = split.next().unwrap_or_default();
+                    let target_str = split.next().unwrap_or_default();
+                    let up_str = split.next().unwrap_or_default();
+                    let fov_str = split.next().unwrap_or_default();
+                    let roll_str = split.next().unwrap_or_default();
+
+                    let eye = parse_vec(eye_str);
+                    let target = if target_str.is_empty() {
+                        None
+                    } else {
+                        Some(parse_vec(target_str))
+                    };
+                    let up = if up_str.is_empty() {
+                        vec(0.0, 1.0, 0.0)
+                    } else {
+                        parse_vec(up_str)
+                    };
+                    let aim = target.unwrap_or(eye + vec(0.0, 0.0, -1.0));
+                    if Camera::look_at(eye, aim, up).is_none() {
+                        println!(
+                            "Invalid camera on line {}: it needs to be aimed away from itself, and not along up",
+                            line_num + 1
+                        );
+                        continue;
+                    }
+                    camera_position = eye;
+                    camera_target = target;
+                    camera_up = up;
+                    if !fov_str.is_empty() {
+                        camera_fov = fov_str.parse::<f32>().ok().and_then(valid_fov);
+                        if camera_fov.is_none() {
+                            println!(
+                                "Invalid camera fov on line {}: expected degrees between 0 and 180",
+                                line_num + 1
+                            );
+                        }
+                    }
+                    camera_roll = roll_str.parse::<f32>().ok().filter(|r| r.is_finite());
+                }
+                "ambient" => ambient = parse_vec(split.next().unwrap_or_default()),
+                "envmap" => {
+                    let path_str = split.next().unwrap_or_default();
+                    match textures.load(scene_dir.join(path_str), TextureKind::Color) {
+                        Ok(index) => background.map = Some(index),
+                        Err(reason) => {
+                            println!("Invalid envmap on line {}: {}", line_num + 1, reason)
+                        }
+                    }
+                }
+                "background" => {
+                    let top_str = split.next().unwrap_or_default();
+                    let bottom_str = split.next().unwrap_or_default();
+
+                    // one color fills the whole sky
+                    let top = parse_vec(top_str);
+                    let bottom = if bottom_str.is_empty() {
+                        top
+                    } else {
+                        parse_vec(bottom_str)
+                    };
+                    background.top = top;
+                    background.bottom = bottom;
+                }
+                "shadow_intensity" => {
+                    let value_str = split.next().unwrap_or_default();
+                    shadow_intensity = value_str.parse::<f32>().unwrap_or(0.0).clamp(0.0, 1.0);
+                }
+                "heightmap" => {
+                    let path_str = split.next().unwrap_or_default();
+                    let origin_str = split.next().unwrap_or_default();
+                    let size_str = split.next().unwrap_or_default();
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+                    let skip_str = split.next().unwrap_or_default();
+
+                    let origin = parse_vec(origin_str);
+                    let size = parse_vec(size_str);
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+
+                    match loaders::heightmap::load_heightmap(
+                        &scene_dir.join(path_str),
+                        origin,
+                        size,
+                        mat,
+                        id,
+                        skip_str == "skip_zero",
+                    ) {
+                        Ok(chunks) => {
+                            for mut chunk in chunks {
+                                for tr in chunk.triangles.iter_mut() {
+                                    *tr = PreparedTriangle::new(place_triangle(
+                                        tr.triangle,
+                                        transforms.last(),
+                                        cull_backfaces,
+                                    ));
+                                }
+                                world.add(chunk);
+                            }
+                        }
+                        Err(reason) => {
+                            println!("Invalid heightmap on line {}: {}", line_num + 1, reason)
+                        }
+                    }
+                }
+                "push_transform" => {
+                    let translate_str = split.next().unwrap_or_default();
+                    let rotate_str = split.next().unwrap_or_default();
+                    let scale_str = split.next().unwrap_or_default();
+
+                    let translate = parse_vec(translate_str);
+                    let rotate = parse_vec(rotate_str);
+                    let scale = if scale_str.is_empty() {
+                        vec(1.0, 1.0, 1.0)
+                    } else {
+                        parse_vec(scale_str)
+                    };
+
+                    let local = Mat4::trs(translate, rotate, scale);
+                    transforms.push(match transforms.last() {
+                        Some(parent) => *parent * local,
+                        None => local,
+                    });
+                }
+                "pop_transform" => {
+                    if transforms.pop().is_none() {
+                        println!(
+                            "pop_transform without push_transform on line {}",
+                            line_num + 1
+                        );
+                    }
+                }
+                "define" => {
+                    let name = split.next().unwrap_or_default().to_string();
+                    if defining.is_some() {
+                        println!("Nested define on line {}", line_num + 1);
+                        continue;
+                    }
+                    // triangles parsed from here until enddefine collect into a fresh list
+                    defining = Some((name, std::mem::take(&mut triangles)));
+                }
+                "enddefine" => match defining.take() {
+                    Some((name, scene_triangles)) => {
+                        let defined = std::mem::replace(&mut triangles, scene_triangles);
+                        let prepared = defined.into_iter().map(PreparedTriangle::new).collect();
+                        definitions.insert(name, Arc::new(prepared));
+                    }
+                    None => println!("enddefine without define on line {}", line_num + 1),
+                },
+                "instance" => {
+                    let name = split.next().unwrap_or_default();
+                    let translate_str = split.next().unwrap_or_default();
+                    let rotate_str = split.next().unwrap_or_default();
+                    let scale_str = split.next().unwrap_or_default();
+                    let id_str = split.next().unwrap_or_default();
+
+                    let Some(triangles) = definitions.get(name) else {
+                        println!("Unknown definition {:?} on line {}", name, line_num + 1);
+                        continue;
+                    };
+                    let translate = parse_vec(translate_str);
+                    let rotate = parse_vec(rotate_str);
+                    let scale = if scale_str.is_empty() {
+                        vec(1.0, 1.0, 1.0)
+                    } else {
+                        parse_vec(scale_str)
+                    };
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+
+                    let local = Mat4::trs(translate, rotate, scale);
+                    let to_world = match transforms.last() {
+                        Some(parent) => *parent * local,
+                        None => local,
+                    };
+                    world.add(Instance {
+                        triangles: Arc::clone(triangles),
+                        to_world,
+                        to_object: to_world.inverse(),
+                        id,
+                    });
+                }
+                "mesh_begin" => {
+                    let mat = load_material(&mut split)?;
+                    let id_str = split.next().unwrap_or_default();
+                    let sides_str = split.next().unwrap_or_default();
+                    let id = id_str.parse::<i8>().unwrap_or(-1);
+
+                    if let Some(mesh) = current_mesh.take() {
+                        println!("Unclosed mesh before line {}", line_num + 1);
+                        world.add(mesh);
+                    }
+                    current_mesh = Some(Mesh {
+                        triangles: Vec::new(),
+                        double_sided: sides_str == "double",
+                        mat,
+                        id,
+                    });
+                }
+                "tri" => {
+                    let a_str = split.next().unwrap_or_default();
+                    let b_str = split.next().unwrap_or_default();
+                    let c_str = split.next().unwrap_or_default();
+
+                    match current_mesh.as_mut() {
+                        Some(mesh) => {
+                            let triangle = Triangle {
+                                a: parse_vec(a_str),
+                                b: parse_vec(b_str),
+                                c: parse_vec(c_str),
+                                vertex_normals: None,
+                                uvs: None,
+                                double_sided: mesh.double_sided,
+                                cull_backfaces,
+                                mat: mesh.mat,
+                                id: mesh.id,
+                            };
+                            mesh.triangles.push(PreparedTriangle::new(place_triangle(
+                                triangle,
+                                transforms.last(),
+                                cull_backfaces,
+                            )));
+                        }
+                        None => println!("tri outside of a mesh on line {}", line_num + 1),
+                    }
+                }
+                "mesh_end" => match current_mesh.take() {
+                    Some(mesh) => world.add(mesh),
+                    None => println!("mesh_end without mesh_begin on line {}", line_num + 1),
+                },
+                _ => println!("Invalid line"),
+            }
+        }
+
+        if let Some(mesh) = current_mesh.take() {
+            println!("Unclosed mesh at end of file");
+            world.add(mesh);
+        }
+
+        if let Some((name, scene_triangles)) = defining.take() {
+            println!("Unclosed define {:?} at end of file", name);
+            triangles = scene_triangles;
+        }
+
+        for triangle in triangles {
+            world.add(PreparedTriangle::new(triangle));
+        }
+        build_accel(&mut world, settings.accel);
+
+        // the command line can move the camera, which stays aimed at the same target
+        let eye = settings.camera_position.unwrap_or(camera_position);
+        let target = camera_target.unwrap_or(eye + vec(0.0, 0.0, -1.0));
+        let mut camera = match Camera::look_at(eye, target, camera_up) {
+            Some(camera) => camera,
+            None => {
+                println!(
+                    "The camera was moved onto its target, so it looks straight down -z instead"
+                );
+                Camera {
+                    position: eye,
+                    right: vec(1.0, 0.0, 0.0),
+                    up: vec(0.0, 1.0, 0.0),
+                    forward: vec(0.0, 0.0, -1.0),
+                    frame_distance: DEFAULT_FRAME_DISTANCE,
+                }
+            }
+        };
+
+        if let Some(fov) = settings.fov.or(camera_fov) {
+            camera = camera.with_fov(fov);
+        }
+        if let Some(roll) = settings.roll.or(camera_roll) {
+            camera = camera.with_roll(roll);
+        }
+
+        if let Some(position) = settings.light_position {
+            if lights.is_empty() {
+                lights.push(DEFAULT_LIGHT);
+            }
+            lights[0].position = position;
+            lights[0].direction = None;
+        }
+        for animation in animations.iter_mut() {
+            animation.light = animation.light.map(|emitter| lights.len() + emitter);
+        }
+        lights.extend(emitters);
+        if lights.is_empty() {
+            lights.push(DEFAULT_LIGHT);
+        }
+
+        return Ok(Scene {
+            world,
+            lights,
+            camera,
+            background,
+            ambient,
+            shadow_intensity,
+            textures: textures.images,
+            moving,
+            animations,
+            settings,
+        });
+    }
+
+    /// Moves everything animated to where it is partway through the animation, ready to render that frame
+    /// # Arguements
+    /// * 'time' - how far through the animation, from 0 at the first frame to 1 at the last
+    pub fn set_frame(&mut self, time: f32) {
+        if self.animations.is_empty() {
+            return;
+        }
+        for animation in &self.animations {
+            let center = animation.sphere.center + animation.travel * time;
+            self.world.objects[animation.index] = Box::new(Sphere {
+                center,
+                ..animation.sphere
+            });
+            if let Some(light) = animation.light {
+                self.lights[light].position = center;
+            }
+        }
+        // everything moved has to be found again in its new place
+        build_accel(&mut self.world, self.settings.accel);
+    }
+
+    /// Renders the scene as seen from its camera, in linear light. `develop` turns it into a finished image. In stereo
+    /// the left eye's view and the right eye's are put side by side, each `width` wide.
+    /// # Arguements
+    /// * 'width' - the image's width in pixels, for each eye in stereo
+    /// * 'height' - the image's height in pixels
+    /// * 'on_pass' - given the render so far every so often during progressive renders. In stereo the eye still
+    ///   being rendered is updated and the other is left as it is
+    pub fn render(&self, width: u32, height: u32, on_pass: &mut dyn FnMut(&Render)) -> Render {
+        if self.settings.stereo.is_none() {
+            return self.render_view(&self.camera, width, height, on_pass);
+        }
+        let eyes = self.eyes();
+        let mut views: Vec<Render> = eyes.iter().map(|_| Render::blank(width, height)).collect();
+        for (i, camera) in eyes.iter().enumerate() {
+            let view = self.render_view(camera, width, height, &mut |partial| {
+                views[i] = partial.clone();
+                on_pass(&Render::side_by_side(&views));
+            });
+            views[i] = view;
+        }
+        return Render::side_by_side(&views);
+    }
+
+    /// The cameras an image is rendered from, left to right. Just the scene's camera, or one for each eye in stereo
+    fn eyes(&self) -> Vec<Camera> {
+        let Some(stereo) = self.settings.stereo else {
+            return vec![self.camera];
+        };
+        return [-0.5, 0.5]
+            .into_iter()
+            .map(|side| {
+                let eye = self.camera.position + self.camera.right * (stereo.separation * side);
+                match stereo.convergence {
+                    // turned in to look at the same point straight ahead of the middle camera
+                    Some(distance) => {
+                        let target = self.camera.position + self.camera.forward * distance;
+                        match Camera::look_at(eye, target, self.camera.up) {
+                            Some(camera) => Camera {
+                                frame_distance: self.camera.frame_distance,
+                                ..camera
+                            },
+                            None => Camera {
+                                position: eye,
+                                ..self.camera
+                            },
+                        }
+                    }
+                    None => Camera {
+                        position: eye,
+                        ..self.camera
+                    },
+                }
+            })
+            .collect();
+    }
+
+    /// Renders the scene as seen from one camera, in linear light
+    /// # Arguements
+    /// * 'camera' - where to look from
+    /// * 'width' - the image's width in pixels
+    /// * 'height' - the image's height in pixels
+    /// * 'on_pass' - given the image so far every so often during progressive renders
+    fn render_view(
+        &self,
+        camera: &Camera,
+        width: u32,
+        height: u32,
+        on_pass: &mut dyn FnMut(&Render),
+    ) -> Render {
+        let image_size = 2;
+        let view = View {
+            camera,
+            pixel_width: image_size as f32 / height as f32,
+            aspect: width as f32 / height as f32,
+        };
+        let samples = self.settings.samples.max(1);
+        // a progressive render already gives every pixel all its samples
+        let adaptive = self
+            .settings
+            .adaptive_threshold
+            .filter(|_| samples > 1 && !self.settings.progressive);
+
+        // kept in linear light until the end, so brights past 1 still stand apart from their neighbours
+        let pixels = (width * height) as usize;
+        let mut pixels = if self.settings.progressive {
+            let progress =
+                Progress::new("Rendering", pixels * samples as usize, self.settings.quiet);
+            let pixels = self.render_passes(&view, width, height, &progress, on_pass);
+            progress.finish();
+            pixels
+        } else {
+            let first_pass = if adaptive.is_some() { 1 } else { samples };
+            let progress = Progress::new("Rendering", pixels, self.settings.quiet);
+            // pixels don't depend on each other, so they're shared out between threads in any order
+            let pixels = render_tiles(width, height, self.settings.tile_size, &progress, |x, y| {
+                self.pixel(&view, x, y, first_pass)
+            });
+            progress.finish();
+            pixels
+        };
+
+        if let Some(threshold) = adaptive {
+            let index = |x: u32, y: u32| (y * width + x) as usize;
+            let mut refine = Vec::new();
+            for y in 0..height {
+                for x in 0..width {
+                    let here = pixels[index(x, y)].color;
+                    let neighbours = [
+                        (x > 0).then(|| index(x - 1, y)),
+                        (x + 1 < width).then(|| index(x + 1, y)),
+                        (y > 0).then(|| index(x, y - 1)),
+                        (y + 1 < height).then(|| index(x, y + 1)),
+                    ];
+                    let contrast = neighbours
+                        .iter()
+                        .flatten()
+                        .map(|&i| {
+                            let d = pixels[i].color - here;
+                            d.x.abs().max(d.y.abs()).max(d.z.abs())
+                        })
+                        .fold(0.0, f32::max);
+                    if contrast > threshold {
+                        refine.push((x, y));
+                    }
+                }
+            }
+            // all found before any are redone, so a refined pixel doesn't change what its neighbours compare to
+            let progress = Progress::new("Refining", refine.len(), self.settings.quiet);
+            let refined: Vec<Pixel> = refine
+                .par_iter()
+                .map(|&(x, y)| {
+                    let pixel = self.pixel(&view, x, y, samples);
+                    progress.advance(1);
+                    pixel
+                })
+                .collect();
+            progress.finish();
+            for (&(x, y), pixel) in refine.iter().zip(refined) {
+                pixels[index(x, y)] = pixel;
+            }
+            println!(
+                "Refined {} of {} pixels ({:.1}%)",
+                refine.len(),
+                pixels.len(),
+                100.0 * refine.len() as f32 / pixels.len() as f32
+            );
+        }
+
+        let mut render = Render::new(&pixels, width, height);
+        if self.settings.denoise {
+            // guided by the surfaces the camera rays already found, rather than tracing them again
+            let colors: Vec<Vec3> = pixels.iter().map(|pixel| pixel.color).collect();
+            let colors = denoise(&colors, &render.surfaces, width, height);
+            render.image = linear_image(&colors, width, height);
+        }
+        return render;
+    }
+
+    /// Renders every pixel one sample at a time, a pass over the whole image for each, handing over the average so
+    /// far after each save interval. Comes out exactly the same as rendering each pixel in one go.
+    /// # Arguements
+    /// * 'view' - the camera and where pixels are on its frame
+    /// * 'width' - the image's width in pixels
+    /// * 'height' - the image's height in pixels
+    /// * 'progress' - told about each pass as it's finished
+    /// * 'on_pass' - given the render so far
+    fn render_passes(
+        &self,
+        view: &View,
+        width: u32,
+        height: u32,
+        progress: &Progress,
+        on_pass: &mut dyn FnMut(&Render),
+    ) -> Vec<Pixel> {
+        let samples = self.settings.samples.max(1);
+        // each pixel keeps its place in its own stream between passes
+        let mut rngs = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                rngs.push(pixel_rng(self.settings.seed, x, y));
+            }
+        }
+        // colors and hits are added up over the passes, and divided by how many there have been
+        let blank = Pixel {
+            color: CLEAR,
+            coverage: 0.0,
+            surface: None,
+        };
+        let mut sums = vec![blank; (width * height) as usize];
+        let average = |sums: &[Pixel], passes: u32| -> Vec<Pixel> {
+            let so_far = 1.0 / passes as f32;
+            return sums
+                .iter()
+                .map(|sum| Pixel {
+                    color: sum.color * so_far,
+                    coverage: sum.coverage * so_far,
+                    ..*sum
+                })
+                .collect();
+        };
+        let mut last_save = Instant::now();
+        for pass in 0..samples {
+            sums.par_iter_mut()
+                .zip(rngs.par_iter_mut())
+                .enumerate()
+                .for_each(|(i, (sum, rng))| {
+                    let (x, y) = (i as u32 % width, i as u32 / width);
+                    let (color, hit) = self.pixel_sample(view, x, y, pass, samples, rng);
+                    sum.color = sum.color + color;
+                    if let Some(hit) = hit {
+                        sum.coverage += 1.0;
+                        if pass == 0 {
+                            sum.surface = Some(self.surface(hit));
+                        }
+                    }
+                });
+            progress.advance(sums.len());
+            let last_pass = pass + 1 == samples;
+            if !last_pass && last_save.elapsed().as_secs_f32() >= self.settings.save_interval {
+                on_pass(&Render::new(&average(&sums, pass + 1), width, height));
+                progress.message(&format!("Saved after {} of {} passes", pass + 1, samples));
+                last_save = Instant::now();
+            }
+        }
+        return average(&sums, samples);
+    }
+
+    /// Turns a render in linear light into the finished image, with the exposure, tone mapping, and gamma applied
+    pub fn develop(&self, linear: &image::Rgb32FImage) -> image::RgbImage {
+        let mut img: image::RgbImage = image::ImageBuffer::new(linear.width(), linear.height());
+        for (pixel, &image::Rgb([r, g, b])) in img.pixels_mut().zip(linear.pixels()) {
+            let color = vec(r, g, b) * self.settings.exposure;
+            let color = match self.settings.tonemap {
+                Tonemap::None => color,
+                Tonemap::Reinhard => vec(
+                    color.x / (1.0 + color.x),
+                    color.y / (1.0 + color.y),
+                    color.z / (1.0 + color.z),
+                ),
+            };
+
+            *pixel = to_rgb8(color, self.settings.gamma);
+        }
+        return img;
+    }
+
+    /// What the rays through one pixel find: their average color in linear light, how many hit something, and
+    /// what the first one hit
+    /// # Arguements
+    /// * 'view' - the camera and where pixels are on its frame
+    /// * 'x' - the pixel's column
+    /// * 'y' - the pixel's row
+    /// * 'samples' - rays to average
+    fn pixel(&self, view: &View, x: u32, y: u32, samples: u32) -> Pixel {
+        let mut rng = pixel_rng(self.settings.seed, x, y);
+        let mut sum = CLEAR;
+        let mut hits = 0;
+        let mut surface = None;
+        for i in 0..samples {
+            let (color, hit) = self.pixel_sample(view, x, y, i, samples, &mut rng);
+            sum = sum + color;
+            if let Some(hit) = hit {
+                hits += 1;
+                if i == 0 {
+                    surface = Some(self.surface(hit));
+                }
+            }
+        }
+        return Pixel {
+            color: sum * (1.0 / samples as f32),
+            coverage: hits as f32 / samples as f32,
+            surface,
+        };
+    }
+
+    /// The color of one of the rays through a pixel, in linear light, and what it hit first. None is the background
+    /// # Arguements
+    /// * 'view' - the camera and where pixels are on its frame
+    /// * 'x' - the pixel's column
+    /// * 'y' - the pixel's row
+    /// * 'index' - which of the pixel's samples this is, from 0
+    /// * 'samples' - how many rays the pixel gets. One goes through the middle, more are spread over all of it
+    /// * 'rng' - the pixel's own random numbers
+    fn pixel_sample(
+        &self,
+        view: &View,
+        x: u32,
+        y: u32,
+        index: u32,
+        samples: u32,
+        rng: &mut Rng,
+    ) -> (Vec3, Option<RayHit>) {
+        let (dx, dy) = self.sample_offset(index, samples, rng);
+        let projection = self.settings.projection;
+        let Some(mut ray) = get_ray(
+            x as f32 + dx,
+            y as f32 + dy,
+            view.camera,
+            projection,
+            view.pixel_width,
+            view.aspect,
+        ) else {
+            // outside what the projection covers, which counts as black
+            return (CLEAR, None);
+        };
+        // a single ray catches everything as the shutter opens, more are spread over the time it's open
+        if self.moving && samples > 1 {
+            ray.time = rng.next_f32();
+        }
+        self.world.stats.count(Counter::Primary);
+        let (color, hit) = self.radiance(view.camera, ray, rng);
+        // dimmed to the limit keeping its hue, so one lucky ray can't light up the whole pixel
+        let color = match self.settings.clamp_radiance {
+            Some(limit) if color.x.max(color.y).max(color.z) > limit => {
+                color * (limit / color.x.max(color.y).max(color.z))
+            }
+            _ => color,
+        };
+        return (color, hit);
+    }
+
+    /// Where one of a pixel's samples goes, from its middle. A single sample goes right through the middle
+    /// # Arguements
+    /// * 'index' - which of the pixel's samples this is, from 0
+    /// * 'samples' - how many rays the pixel gets
+    /// * 'rng' - the pixel's own random numbers
+    fn sample_offset(&self, index: u32, samples: u32, rng: &mut Rng) -> (f32, f32) {
+        if samples == 1 {
+            return (0.0, 0.0);
+        }
+        let (sx, sy) = self.settings.sampler.point(index, samples, rng);
+        return (sx - 0.5, sy - 0.5);
+    }
+
+    /// What a camera ray hit, as the denoiser and the extra images see it
+    /// # Arguements
+    /// * 'hit' - where the ray hit
+    fn surface(&self, hit: RayHit) -> Surface {
+        // with the normal and bump maps, so their detail counts as edges and isn't smoothed away
+        let hit = apply_bump(apply_normal_map(hit, &self.textures));
+        return Surface {
+            normal: hit.surface_normal,
+            depth: hit.t,
+            id: hit.id,
+        };
+    }
+
+    /// The color one ray from the camera sees, through the lens if there is one, and what it hit first. None is the
+    /// background
+    /// # Arguements
+    /// * 'camera' - the camera the ray leaves
+    /// * 'ray' - the ray from the camera through a spot in the pixel
+    /// * 'rng' - random numbers for the lens and everything the ray meets
+    fn radiance(&self, camera: &Camera, ray: Ray, rng: &mut Rng) -> (Vec3, Option<RayHit>) {
+        let ray = if self.settings.aperture > 0.0 {
+            self.through_lens(camera, ray, rng)
+        } else {
+            ray
+        };
+        // the same as trace, keeping the hit for the pixel's coverage and surface
+        let hit = find_closest_hit(ray, -1, &self.world);
+        if hit.t < 0.0 || hit.t == f32::MAX {
+            return (
+                self.background.along(ray.direction_vector, &self.textures),
+                None,
+            );
+        }
+        let color = self.shade_hit(ray, hit, self.settings.reflection_depth, true, WHITE, rng);
+        return (color, Some(hit));
+    }
+
+    /// Turns a pinhole camera ray into one through a thin lens. It starts from a random point on the lens and is aimed
+    /// at where the pinhole ray meets the plane in focus, so only things on that plane stay sharp.
+    /// # Arguements
+    /// * 'camera' - the camera the lens is on
+    /// * 'ray' - the ray from the camera through the pixel
+    /// * 'rng' - picks the point on the lens
+    fn through_lens(&self, camera: &Camera, ray: Ray, rng: &mut Rng) -> Ray {
+        // through a flat frame the plane in focus is flat too, so rays towards the edge of the image go further to
+        // reach it. A fisheye sees too far round for that, so it focuses at the same distance every way
+        let along = match self.settings.projection {
+            Projection::Perspective => {
+                self.settings.focal_distance / (ray.direction_vector * camera.forward)
+            }
+            Projection::Fisheye { .. } | Projection::Equirect => self.settings.focal_distance,
+        };
+        let focus = ray.start_pos + ray.direction_vector * along;
+        let (dx, dy) = in_unit_disk(rng);
+        let aperture = self.settings.aperture;
+        let start = camera.position + camera.right * (dx * aperture) + camera.up * (dy * aperture);
+        return Ray {
+            start_pos: start,
+            direction_vector: norm(focus - start),
+            time: ray.time,
+        };
+    }
+
+    /// The color seen along a ray, following mirror and glass bounces until the bounce budget runs out
+    /// # Arguements
+    /// * 'ray' - the ray to follow
+    /// * 'id' - id of an object to ignore, usually the one the ray is leaving
+    /// * 'depth' - how many more bounces are allowed. Mirrors that run out show their own shading, and glass goes
+    ///   black
+    /// * 'primary' - whether this ray comes straight from the camera. Highlights are only added for those,
+    ///   so reflections and glass don't pick up extra ones.
+    /// * 'throughput' - how much of what the ray sees makes it back to the camera, per channel. White for camera
+    ///   rays, and scaled down by each surface the path bounces off on the way
+    /// * 'rng' - random numbers for rough reflections
+    fn trace(
+        &self,
+        ray: Ray,
+        id: i8,
+        depth: i32,
+        primary: bool,
+        throughput: Vec3,
+        rng: &mut Rng,
+    ) -> Vec3 {
+        let ray_hit = find_closest_hit(ray, id, &self.world);
+        if ray_hit.t < 0.0 || ray_hit.t == f32::MAX {
+            return self.background.along(ray.direction_vector, &self.textures);
+        }
+        return self.shade_hit(ray, ray_hit, depth, primary, throughput, rng);
+    }
+
+    /// The color of a surface a ray has hit, as trace sees it
+    /// # Arguements
+    /// * 'ray' - the ray that hit it
+    /// * 'ray_hit' - where it was hit
+    /// * 'depth' - how many more bounces are allowed
+    /// * 'primary' - whether the ray comes straight from the camera
+    /// * 'throughput' - how much of the color makes it back to the camera, per channel
+    /// * 'rng' - random numbers for rough reflections and bounced light
+    fn shade_hit(
+        &self,
+        ray: Ray,
+        ray_hit: RayHit,
+        depth: i32,
+        primary: bool,
+        throughput: Vec3,
+        rng: &mut Rng,
+    ) -> Vec3 {
+        let ray_hit = apply_bump(apply_normal_map(ray_hit, &self.textures));
+
+        let color = ray_hit
+            .mat
+            .albedo(ray_hit.intersect, ray_hit.u, ray_hit.v, &self.textures);
+        match ray_hit.mat.t {
+            geometry::MaterialType::Matte { .. } => {
+                return self.shade(color, ray_hit, ray.time, depth, primary, throughput, rng);
+            }
+            geometry::MaterialType::Glossy {
+                specular,
+                specular_color,
+                reflectivity,
+                roughness,
+                ..
+            } => {
+                let reflecting = reflectivity > 0.0 && depth > 0;
+                // the surface's own shading only shows through as much as it doesn't reflect
+                let kept = if reflecting { 1.0 - reflectivity } else { 1.0 };
+                // shared with the highlights, so they're shadowed by the same rays as the rest of the surface
+                let visibility = self.light_visibility(ray_hit, ray.time, rng);
+                let mut lit = self.shade_visible(
+                    color,
+                    ray_hit,
+                    ray.time,
+                    depth,
+                    primary,
+                    throughput * kept,
+                    &visibility,
+                    rng,
+                );
+                if reflecting {
+                    let reflected = self.glossy_reflection(
+                        ray,
+                        ray_hit,
+                        roughness,
+                        depth,
+                        primary,
+                        mul_elem(throughput, specular_color) * reflectivity,
+                        rng,
+                    );
+                    lit = lit * kept + mul_elem(reflected, specular_color) * reflectivity;
+                }
+                if !primary {
+                    return lit;
+                }
+                let model = specular.unwrap_or(self.settings.specular);
+                for (&light, &visible) in self.lights.iter().zip(&visibility) {
+                    if !light.reaches(ray_hit.intersect) {
+                        continue;
+                    }
+                    lit = lit
+                        + specular_calc(
+                            ray_hit,
+                            light,
+                            ray.direction_vector * -1.0,
+                            model,
+                            visible,
+                        );
+                }
+                return lit;
+            }
+            geometry::MaterialType::Reflective {
+                reflectivity, fuzz, ..
+            } => {
+                // out of bounces, so the surface shows its own shading rather than going black
+                if depth <= 0 {
+                    return self.shade(color, ray_hit, ray.time, depth, primary, throughput, rng);
+                }
+                let k = reflectivity;
+                // what it reflects is tinted by its color, and only makes up the reflective part of it
+                let reflected_throughput = mul_elem(throughput, color) * k;
+                let mirror = norm(
+                    ray_hit.surface_normal
+                        * (-2.0 * (ray.direction_vector * ray_hit.surface_normal))
+                        + ray.direction_vector,
+                );
+                let reflected = if fuzz <= 0.0 {
+                    let bounce = Ray {
+                        start_pos: ray_hit.intersect,
+                        direction_vector: mirror,
+                        time: ray.time,
+                    };
+                    self.world.stats.count(Counter::Reflection);
+                    self.trace(
+                        bounce,
+                        ray_hit.id,
+                        depth - 1,
+                        false,
+                        reflected_throughput,
+                        rng,
+                    )
+                } else {
+                    // one bounce in a random direction nearby. Each pixel already averages its samples, so
+                    // that's enough without rough metals facing each other multiplying the ray count
+                    let direction = norm(mirror + rng.in_unit_sphere() * fuzz);
+                    // jittered to the wrong side of the surface, so it's absorbed
+                    if (direction * ray_hit.surface_normal) * (mirror * ray_hit.surface_normal)
+                        <= 0.0
+                    {
+                        CLEAR
+                    } else {
+                        let bounce = Ray {
+                            start_pos: ray_hit.intersect,
+                            direction_vector: direction,
+                            time: ray.time,
+                        };
+                        self.world.stats.count(Counter::Reflection);
+                        self.trace(
+                            bounce,
+                            ray_hit.id,
+                            depth - 1,
+                            false,
+                            reflected_throughput,
+                            rng,
+                        )
+                    }
+                };
+
+                // colored mirrors tint what they reflect, so a gold ball reflects everything gold
+                let reflected = mul_elem(reflected, color);
+
+                // a perfect mirror shows only what it reflects
+                if k >= 1.0 {
+                    return reflected;
+                }
+                let local = self.shade(
+                    color,
+                    ray_hit,
+                    ray.time,
+                    depth,
+                    primary,
+                    throughput * (1.0 - k),
+                    rng,
+                );
+                return local * (1.0 - k) + reflected * k;
+            }
+            geometry::MaterialType::Refractive {
+                ior, absorption, ..
+            } => {
+                if depth <= 0 {
+                    return vec(0.0, 0.0, 0.0);
+                }
+                let direction = refract(ray.direction_vector, ray_hit.surface_normal, ior);
+
+                // nudged off the surface instead of ignoring the object, since the ray usually
+                // has to leave through the same object it went into
+                let through = Ray {
+                    start_pos: ray_hit.intersect + direction * SURFACE_OFFSET,
+                    direction_vector: direction,
+                    time: ray.time,
+                };
+                self.world.stats.count(Counter::Refraction);
+                // leaving the object, so the ray has just crossed its inside to get here
+                if ray.direction_vector * ray_hit.surface_normal > 0.0 {
+                    let seen = self.trace(through, NO_ID, depth - 1, false, throughput, rng);
+                    return absorb(seen, absorption, ray_hit.t);
+                }
+                // tinted on the way in, the same as light passing through it, so it's colored once rather than
+                // again on the way out
+                let seen = self.trace(
+                    through,
+                    NO_ID,
+                    depth - 1,
+                    false,
+                    mul_elem(throughput, color),
+                    rng,
+                );
+                return mul_elem(seen, color);
+            }
+            geometry::MaterialType::Emissive { intensity, .. } => {
+                // can go over 1, which to_rgb8 clamps to full brightness
+                return color * intensity;
+            }
+        }
+    }
+
+    /// The blurred reflection off a glossy surface, averaged over rays scattered within a cone around the mirror
+    /// direction. Only camera hits get more than one, so glossy surfaces seen in each other don't multiply the
+    /// ray count at every bounce
+    /// # Arguements
+    /// * 'ray' - the ray that hit the surface
+    /// * 'ray_hit' - where it was hit
+    /// * 'roughness' - how wide the cone is, from 0 for a sharp mirror to 1 for right angles from it
+    /// * 'depth' - how many more bounces are allowed, counting this one
+    /// * 'primary' - whether the hit was seen straight from the camera
+    /// * 'throughput' - how much of the reflection makes it back to the camera, per channel
+    /// * 'rng' - scatters the rays
+    #[allow(clippy::too_many_arguments)]
+    fn glossy_reflection(
+        &self,
+        ray: Ray,
+        ray_hit: RayHit,
+        roughness: f32,
+        depth: i32,
+        primary: bool,
+        throughput: Vec3,
+        rng: &mut Rng,
+    ) -> Vec3 {
+        let normal = ray_hit.surface_normal;
+        let mirror = norm(normal * (-2.0 * (ray.direction_vector * normal)) + ray.direction_vector);
+        let samples = if primary && roughness > 0.0 {
+            self.settings.glossy_samples.max(1)
+        } else {
+            1
+        };
+        let max_angle = roughness * std::f32::consts::FRAC_PI_2;
+        let mut sum = CLEAR;
+        for i in 0..samples {
+            let (u, v) = self.settings.sampler.point(i, samples, rng);
+            let direction = in_cone(mirror, max_angle, u, v);
+            // scattered to the wrong side of the surface, so it's absorbed
+            if (direction * normal) * (mirror * normal) <= 0.0 {
+                continue;
+            }
+            let bounce = Ray {
+                start_pos: ray_hit.intersect,
+                direction_vector: direction,
+                time: ray.time,
+            };
+            self.world.stats.count(Counter::Reflection);
+            sum = sum + self.trace(bounce, ray_hit.id, depth - 1, false, throughput, rng);
+        }
+        return sum * (1.0 / samples as f32);
+    }
+
+    /// A surface's color under the ambient light plus whatever each light adds, without any highlights. Can add up
+    /// past 1, which is left for to_rgb8 to clamp.
+    /// # Arguements
+    /// * 'color' - the surface's albedo at the hit
+    /// * 'r' - the hit being shaded
+    /// * 'time' - when the ray that hit it was cast, so shadows are cast by moving things where they were then
+    /// * 'depth' - how many more bounces are allowed. Path tracing gathers bounced light in place of the ambient
+    ///   light until they run out
+    /// * 'primary' - whether the hit was seen straight from the camera. Only those get ambient occlusion
+    /// * 'throughput' - how much of the surface's color makes it back to the camera, per channel
+    /// * 'rng' - random numbers for soft shadows, ambient occlusion, and bounced light
+    #[allow(clippy::too_many_arguments)]
+    fn shade(
+        &self,
+        color: Vec3,
+        r: RayHit,
+        time: f32,
+        depth: i32,
+        primary: bool,
+        throughput: Vec3,
+        rng: &mut Rng,
+    ) -> Vec3 {
+        let visibility = self.light_visibility(r, time, rng);
+        return self.shade_visible(color, r, time, depth, primary, throughput, &visibility, rng);
+    }
+
+    /// Shades a hit the same as shade, with how much of each light reaches it already worked out
+    /// # Arguements
+    /// * 'color' - the surface's albedo at the hit
+    /// * 'r' - the hit being shaded
+    /// * 'time' - when the ray that hit it was cast
+    /// * 'depth' - how many more bounces are allowed
+    /// * 'primary' - whether the hit was seen straight from the camera
+    /// * 'throughput' - how much of the surface's color makes it back to the camera, per channel
+    /// * 'visibility' - how much of each light reaches the hit, from light_visibility
+    /// * 'rng' - random numbers for ambient occlusion and bounced light
+    #[allow(clippy::too_many_arguments)]
+    fn shade_visible(
+        &self,
+        color: Vec3,
+        r: RayHit,
+        time: f32,
+        depth: i32,
+        primary: bool,
+        throughput: Vec3,
+        visibility: &[Vec3],
+        rng: &mut Rng,
+    ) -> Vec3 {
+        let mut light_sum = if self.settings.mode == Mode::PathTrace && depth > 0 {
+            self.gather(color, r, time, depth, throughput, rng)
+        } else if primary {
+            self.ambient * self.ambient_occlusion(r, time, rng)
+        } else {
+            self.ambient
+        };
+        for (&light, &visible) in self.lights.iter().zip(visibility) {
+            if !light.reaches(r.intersect) {
+                continue;
+            }
+            light_sum = light_sum + mul_elem(diffuse_calc(r, light, visible), light.color);
+        }
+        return mul_elem(color, light_sum);
+    }
+
+    /// How much of each light reaches a hit, in the same order as the scene's lights. Lights that can't reach it
+    /// at all get none, without casting a shadow ray
+    /// # Arguements
+    /// * 'r' - the hit being lit
+    /// * 'time' - when the ray that hit it was cast
+    /// * 'rng' - picks the points on area lights
+    fn light_visibility(&self, r: RayHit, time: f32, rng: &mut Rng) -> Vec<Vec3> {
+        return self
+            .lights
+            .iter()
+            .map(|&light| {
+                if !light.reaches(r.intersect) {
+                    return CLEAR;
+                }
+                return self.visibility(r, light, time, rng);
+            })
+            .collect();
+    }
+
+    /// Light bounced onto a hit off everything around it, from one direction picked in proportion to how much
+    /// light from it counts, so the average over many is the whole of it
+    /// # Arguements
+    /// * 'color' - the surface's albedo at the hit, which all the light gathered is scaled by
+    /// * 'r' - the hit being lit
+    /// * 'time' - when the ray that hit it was cast
+    /// * 'depth' - how many more bounces are allowed, counting this one
+    /// * 'throughput' - how much of the surface's color makes it back to the camera, per channel
+    /// * 'rng' - picks the direction, and whether to go on at all
+    fn gather(
+        &self,
+        color: Vec3,
+        r: RayHit,
+        time: f32,
+        depth: i32,
+        throughput: Vec3,
+        rng: &mut Rng,
+    ) -> Vec3 {
+        // the light picked up from here on is scaled by this surface's color as well as everything before it.
+        // Cosine weighted directions make the albedo the whole of a bounce's weight
+        let throughput = mul_elem(throughput, color);
+        // nothing this path could still pick up would show
+        if throughput.x.max(throughput.y).max(throughput.z) <= 0.0 {
+            return CLEAR;
+        }
+
+        // past the first few bounces, paths that have been dimmed so much they'd add little are mostly cut short.
+        // The ones that go on count for more to make up for it, so the average comes out the same
+        let mut survival = 1.0;
+        if self.settings.reflection_depth - depth >= self.settings.rr_depth {
+            survival = throughput.x.max(throughput.y).max(throughput.z).min(1.0);
+            if rng.next_f32() >= survival {
+                return CLEAR;
+            }
+        }
+        let throughput = throughput * (1.0 / survival);
+
+        let bounce = Ray {
+            start_pos: r.intersect,
+            direction_vector: cosine_hemisphere(r.surface_normal, rng),
+            time,
+        };
+        self.world.stats.count(Counter::Bounce);
+        let hit = find_closest_hit(bounce, r.id, &self.world);
+        let gathered = if hit.t < 0.0 || hit.t == f32::MAX {
+            self.background
+                .along(bounce.direction_vector, &self.textures)
+        } else if let geometry::MaterialType::Emissive { .. } = hit.mat.t {
+            // glowing spheres already light things as lights, so seeing one here would count it twice. Other
+            // glowing shapes don't light anything in either mode
+            CLEAR
+        } else {
+            self.shade_hit(bounce, hit, depth - 1, false, throughput, rng)
+        };
+        return gathered * (1.0 / survival);
+    }
+
+    /// How much of the open sky above a hit isn't blocked by anything within the occlusion radius, from 0 to 1.
+    /// Always 1 when ambient occlusion is off.
+    /// # Arguements
+    /// * 'r' - the hit being shaded
+    /// * 'time' - when the ray that hit it was cast
+    /// * 'rng' - picks the directions to test
+    fn ambient_occlusion(&self, r: RayHit, time: f32, rng: &mut Rng) -> f32 {
+        let samples = self.settings.ao_samples;
+        if samples == 0 {
+            return 1.0;
+        }
+        let mut open = 0;
+        for _ in 0..samples {
+            let probe = Ray {
+                start_pos: r.intersect,
+                direction_vector: cosine_hemisphere(r.surface_normal, rng),
+                time,
+            };
+            self.world.stats.count(Counter::Occlusion);
+            if !is_occluded(probe, self.settings.ao_radius, r.id, &self.world) {
+                open += 1;
+            }
+        }
+        return open as f32 / samples as f32;
+    }
+
+    /// How much of a light reaches a hit past whatever is in the way, per channel from 0 to 1. Light that doesn't
+    /// make it through is made up to the hit's shadow intensity.
+    /// # Arguements
+    /// * 'r' - the hit being lit
+    /// * 'light' - the light being tested. Area lights average jittered rays to points across them
+    /// * 'time' - when the ray that hit it was cast
+    /// * 'rng' - picks the points on area lights
+    fn visibility(&self, r: RayHit, light: Light, time: f32, rng: &mut Rng) -> Vec3 {
+        let through = match light.area {
+            // a zero area light is a point, and gets exactly the point light's one ray
+            Some((u, v)) if mag(&cross(u, v)) > 0.0 => {
+                let samples = light.samples.unwrap_or(self.settings.shadow_samples).max(1);
+                let mut sum = CLEAR;
+                for i in 0..samples {
+                    let (su, sv) = self.settings.sampler.point(i, samples, rng);
+                    let target = light.target(r.intersect) + u * (su - 0.5) + v * (sv - 0.5);
+                    sum = sum
+                        + light_transmittance(
+                            r.intersect,
+                            target,
+                            r.surface_normal,
+                            time,
+                            &self.world,
+                            &self.textures,
+                        );
+                }
+                sum * (1.0 / samples as f32)
+            }
+            _ => light_transmittance(
+                r.intersect,
+                light.target(r.intersect),
+                r.surface_normal,
+                time,
+                &self.world,
+                &self.textures,
+            ),
+        };
+        let shadow = r.mat.shadow.unwrap_or(self.shadow_intensity);
+        return through + (vec(1.0, 1.0, 1.0) - through) * shadow;
+    }
+}
+
+/// The random numbers for one pixel. Each pixel gets its own stream, so it comes out the same whatever order pixels
+/// are done in
+fn pixel_rng(seed: u64, x: u32, y: u32) -> Rng {
+    // hashed a part at a time, so nearby pixels and nearby seeds don't get overlapping streams
+    return Rng::new(splitmix64(splitmix64(seed) ^ x as u64) ^ y as u64);
+}
+
+/// Works out the bounds of every object in the world, and builds whatever speeds up finding what rays hit
+pub fn build_accel(world: &mut World, accel: Accel) {
+    world.build_bounds();
+    match accel {
+        Accel::Bvh => world.bvh = Some(build_bvh(world)),
+        Accel::Grid => world.grid = Some(build_grid(world)),
+        Accel::None => world.spheres = Some(SphereSoA::new(world)),
+    }
+}
+
+/// Puts linear colors, row by row, into an image without changing them
+fn linear_image(colors: &[Vec3], width: u32, height: u32) -> image::Rgb32FImage {
+    let mut img: image::Rgb32FImage = image::ImageBuffer::new(width, height);
+    for (pixel, &c) in img.pixels_mut().zip(colors) {
+        *pixel = image::Rgb([c.x, c.y, c.z]);
+    }
+    return img;
+}
+
+/// Turns a linear color into a pixel. Each channel is clamped to 0 to 1 once here, so everything before can add
+/// up past 1 freely.
+/// # Arguements
+/// * 'c' - the color, in linear light
+/// * 'gamma' - gamma to encode with, since screens expect it
+fn to_rgb8(c: Vec3, gamma: f32) -> image::Rgb<u8> {
+    let encode = |channel: f32| (channel.clamp(0.0, 1.0).powf(1.0 / gamma) * 255.0) as u8;
+    return image::Rgb([encode(c.x), encode(c.y), encode(c.z)]);
+}
+
+/// Constant null Material used as a default
+const NUL: geometry::Material = geometry::Material::new(geometry::MaterialType::Matte {
+    albedo: Albedo::Solid(Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    }),
+});
+
+/// Id no object has. Flags a miss, and ignoring it lets a ray hit anything.
+const NO_ID: i8 = -2;
+
+/// Most surfaces a shadow ray passes through before the light counts as blocked
+const MAX_SHADOW_CROSSINGS: i32 = 16;
+
+/// How far rays that can't ignore the surface they start on are pushed off it
+const SURFACE_OFFSET: f32 = 1e-3;
+
+/// Id given to the ground plane. Negative ids aren't used by scene files (-1 and -2 are already reserved)
+const GROUND_ID: i8 = -3;
+
+/// Returns the ray out of the camera through a given pixel, or None if the projection doesn't cover it. Pixels are
+/// placed on an image frame 2 units tall, and as wide as the image's aspect ratio makes it, so wider images see
+/// more to the sides rather than stretching.
+/// # Arguements
+/// * 'x' - A float for the x pixel
+/// * 'y' - A float for the y pixel
+/// * 'camera' - where the ray should emmenate from, and which way the frame faces
+/// * 'projection' - how a spot on the frame becomes a direction
+/// * 'pixel_width' - The width in arbitrary units of a given pixel in our final image
+/// * 'aspect' - The image's width over its height
+fn get_ray(
+    x: f32,
+    y: f32,
+    camera: &Camera,
+    projection: Projection,
+    pixel_width: f32,
+    aspect: f32,
+) -> Option<Ray> {
+    let img_x = (x * pixel_width) + (pixel_width / 2.0) - aspect;
+    let img_y = -((y * pixel_width) + (pixel_width / 2.0) - 1.0);
+    let direction: Vec3 = match projection {
+        Projection::Perspective => {
+            norm(camera.right * img_x + camera.up * img_y + camera.forward * camera.frame_distance)
+        }
+        Projection::Fisheye { fov } => {
+            let radius = (img_x * img_x + img_y * img_y).sqrt();
+            if radius > 1.0 {
+                return None;
+            }
+            // angle from straight ahead grows evenly with distance from the middle, and the spot's direction
+            // round the middle is kept
+            let angle = radius * (fov * 0.5).to_radians();
+            let around = f32::atan2(img_y, img_x);
+            let sideways = camera.right * around.cos() + camera.up * around.sin();
+            norm(camera.forward * angle.cos() + sideways * angle.sin())
+        }
+        Projection::Equirect => {
+            // pixel centers never quite reach the poles, and straight up or down is a fine direction anyway
+            let longitude = img_x / aspect * std::f32::consts::PI;
+            let latitude = img_y * std::f32::consts::FRAC_PI_2;
+            let level = camera.forward * longitude.cos() + camera.right * longitude.sin();
+            norm(level * latitude.cos() + camera.up * latitude.sin())
+        }
+    };
+    return Some(Ray {
+        start_pos: camera.position,
+        direction_vector: direction,
+        time: 0.0,
+    });
+}
+
+/// Finds the closest surface to a ray's origin along its direction. Used to see what a Ray would hit first
+/// # Arguements
+/// * 'ray' - The ray we want to test
+/// * 'id' - An id of objects to ignore. Used to stop shadow/reflection acne
+/// * 'world' - the objects to check the ray against
+pub fn find_closest_hit(ray: Ray, id: i8, world: &World) -> RayHit {
+    let mut r: RayHit = RayHit {
+        t: f32::MAX,
+        mat: NUL,
+        intersect: ray.start_pos,
+        surface_normal: ray.start_pos,
+        id: NO_ID,
+        u: 0.0,
+        v: 0.0,
+        tangents: None,
+    };
+
+    if let Some(bvh) = &world.bvh {
+        return bvh_closest_hit(bvh, ray, id, world).unwrap_or(r);
+    }
+    if let Some(grid) = &world.grid {
+        return grid_closest_hit(grid, ray, id, world).unwrap_or(r);
+    }
+
+    if let Some(spheres) = &world.spheres {
+        // the closest sphere from one pass over them all, with ties going to whichever came first
+        let mut best: Option<(f32, usize)> = None;
+        for (t, i) in spheres.hits(ray, id, f32::MAX, &world.stats) {
+            let best_t = best.map_or(f32::MAX, |(best_t, _)| best_t);
+            // still checked against its box like everything else, which turns down the odd grazing hit far away
+            // that only comes from rounding
+            if t < best_t && boxed(world, i, ray, best_t) {
+                best = Some((t, i));
+            }
+        }
+        let mut best_hit = None;
+        for &i in spheres.others() {
+            let object = &world.objects[i];
+            if object.id() == id {
+                continue;
+            }
+            // a hit level with the best sphere still wins if it came first, same as testing everything in order
+            let t_max = best.map_or(f32::MAX, |(t, _)| t.next_up());
+            if !boxed(world, i, ray, t_max) {
+                continue;
+            }
+            world.stats.count(Counter::IntersectionTest);
+            if let Some(hit) = object.hit(&ray, t_max) {
+                if best.is_none_or(|(t, j)| hit.t < t || i < j) {
+                    best = Some((hit.t, i));
+                    best_hit = Some(hit);
+                }
+            }
+        }
+        return match (best_hit, best) {
+            (Some(hit), _) => hit,
+            (None, Some((_, i))) => world.objects[i].hit(&ray, f32::MAX).unwrap_or(r),
+            (None, None) => r,
+        };
+    }
+
+    for (object, bounds) in world.objects.iter().zip(&world.bounds) {
+        if object.id() == id {
+            continue;
+        }
+        // cheap box check first, which also skips anything that can't beat the closest hit so far
+        if let Some(b) = bounds {
+            if !ray_aabb_intersect(b, &ray, r.t) {
+                continue;
+            }
+        }
+        world.stats.count(Counter::IntersectionTest);
+        if let Some(hit) = object.hit(&ray, r.t) {
+            r = hit;
+        }
+    }
+
+    return r;
+}
+
+/// Whether a ray passes through an object's box before `t_max`. Things without one, like planes, always pass
+fn boxed(world: &World, index: usize, ray: Ray, t_max: f32) -> bool {
+    return match &world.bounds[index] {
+        Some(b) => ray_aabb_intersect(b, &ray, t_max),
+        None => true,
+    };
+}
+
+/// Whether anything at all is in the way along a ray before `max_dist`, stopping at the first thing found rather than
+/// working out which is closest
+/// # Arguements
+/// * 'ray' - ray to trace
+/// * 'max_dist' - how far along the ray to look
+/// * 'id' - id of an object to ignore
+/// * 'world' - the objects to test against
+pub fn is_occluded(ray: Ray, max_dist: f32, id: i8, world: &World) -> bool {
+    return any_hit(ray, max_dist, id, world, &mut |_| true);
+}
+
+/// Same as `is_occluded`, but only counting hits that `stop` says do, in no particular order
+fn any_hit(
+    ray: Ray,
+    max_dist: f32,
+    id: i8,
+    world: &World,
+    stop: &mut dyn FnMut(&RayHit) -> bool,
+) -> bool {
+    if let Some(bvh) = &world.bvh {
+        return bvh_any_hit(bvh, ray, max_dist, id, world, stop);
+    }
+    if let Some(grid) = &world.grid {
+        return grid_any_hit(grid, ray, max_dist, id, world, stop);
+    }
+
+    if let Some(spheres) = &world.spheres {
+        for (_, i) in spheres.hits(ray, id, max_dist, &world.stats) {
+            if !boxed(world, i, ray, max_dist) {
+                continue;
+            }
+            let hit = world.objects[i].hit(&ray, max_dist);
+            if hit.is_some_and(|hit| stop(&hit)) {
+                return true;
+            }
+        }
+    }
+
+    let mut blocks = |i: usize| {
+        let object = &world.objects[i];
+        if object.id() == id {
+            return false;
+        }
+        if !boxed(world, i, ray, max_dist) {
+            return false;
+        }
+        world.stats.count(Counter::IntersectionTest);
+        return object.hit(&ray, max_dist).is_some_and(|hit| stop(&hit));
+    };
+    return match &world.spheres {
+        Some(spheres) => spheres.others().iter().any(|&i| blocks(i)),
+        None => (0..world.objects.len()).any(blocks),
+    };
+}
+
+/// How much of the light gets from a point to the light, per channel from 0 for none to 1 for all of it. Emissive
+/// surfaces let it all through, and glass lets through its own color, less a little bounced off each surface.
+/// Anything else blocks it.
+/// # Arguements
+/// * 'pos' - the point being lit
+/// * 'light' - where the light is
+/// * 'normal' - the surface normal at the point, which the ray starts just off of
+/// * 'time' - when in the shutter to look, for anything moving
+/// * 'world' - the objects that might be in the way
+/// * 'textures' - the images materials refer to, for tinting by textured glass
+fn light_transmittance(
+    pos: Vec3,
+    light: Vec3,
+    normal: Vec3,
+    time: f32,
+    world: &World,
+    textures: &[image::Rgb32FImage],
+) -> Vec3 {
+    // nudged off the surface on the light's side rather than ignoring the whole object, so concave shapes and
+    // meshes sharing an id can still shadow themselves
+    let side = if normal * (light - pos) < 0.0 {
+        -1.0
+    } else {
+        1.0
+    };
+    let mut start = pos + normal * (SURFACE_OFFSET * side);
+    let mut through = vec(1.0, 1.0, 1.0);
+    world.stats.count(Counter::Shadow);
+    // most shadow rays either reach the light untouched or hit something solid, and neither needs the closest hit
+    // worked out. Only glass and glowing things in the way mean following the ray surface by surface.
+    let to_light = light - start;
+    let straight = Ray {
+        start_pos: start,
+        direction_vector: norm(to_light),
+        time,
+    };
+    let mut see_through = false;
+    let blocked = any_hit(straight, mag(&to_light), NO_ID, world, &mut |hit| {
+        let passes = matches!(
+            hit.mat.t,
+            geometry::MaterialType::Emissive { .. } | geometry::MaterialType::Refractive { .. }
+        );
+        see_through |= passes;
+        return !passes;
+    });
+    if blocked {
+        return vec(0.0, 0.0, 0.0);
+    }
+    if !see_through {
+        return through;
+    }
+    for _ in 0..MAX_SHADOW_CROSSINGS {
+        let to_light = light - start;
+        let to_light_norm = norm(to_light);
+        let light_blocker = find_closest_hit(
+            Ray {
+                start_pos: start,
+                direction_vector: to_light_norm,
+                time,
+            },
+            NO_ID,
+            world,
+        );
+
+        if !(light_blocker.t > 0.0 && mag(&to_light) > light_blocker.t) {
+            return through;
+        }
+        match light_blocker.mat.t {
+            geometry::MaterialType::Emissive { .. } => {}
+            geometry::MaterialType::Refractive {
+                ior, absorption, ..
+            } => {
+                // share of light bounced off the surface, taken head on since that's most of it
+                let bounced = ((ior - 1.0) / (ior + 1.0)).powi(2);
+                through = through * (1.0 - bounced);
+                // tinted on the way in, so a glass ball colors the light once rather than twice, and soaked up
+                // on the way out by however much glass it went through
+                if to_light_norm * light_blocker.surface_normal < 0.0 {
+                    let tint = light_blocker.mat.albedo(
+                        light_blocker.intersect,
+                        light_blocker.u,
+                        light_blocker.v,
+                        textures,
+                    );
+                    through = mul_elem(through, tint);
+                } else {
+                    through = absorb(through, absorption, light_blocker.t);
+                }
+            }
+            _ => return vec(0.0, 0.0, 0.0),
+        }
+        // step past the surface and keep looking
+        start = light_blocker.intersect + to_light_norm * SURFACE_OFFSET;
+    }
+    // more surfaces in the way than are worth following
+    return vec(0.0, 0.0, 0.0);
+}
+
+/// Light left after passing through some distance of a material, following Beer-Lambert
+/// # Arguements
+/// * 'color' - the light going in
+/// * 'absorption' - how much of each channel the material soaks up per unit of distance
+/// * 'distance' - how far the light went through it
+fn absorb(color: Vec3, absorption: Vec3, distance: f32) -> Vec3 {
+    return vec(
+        color.x * (-absorption.x * distance).exp(),
+        color.y * (-absorption.y * distance).exp(),
+        color.z * (-absorption.z * distance).exp(),
+    );
+}
+
+/// How much of the light reaches a hit, per channel, as a multiple of the light's color. Nothing when it faces away
+/// from the light.
+/// # Arguements
+/// * 'r' - the hit being lit
+/// * 'light' - the light, which fades with distance by its attenuation
+/// * 'visibility' - how much of the light gets past whatever is in the way
+fn diffuse_calc(r: RayHit, light: Light, visibility: Vec3) -> Vec3 {
+    let to_light = light.target(r.intersect) - r.intersect;
+    let to_light_norm = norm(to_light);
+    let facing = f32::clamp(to_light_norm * r.surface_normal, 0.0, 1.0);
+
+    return visibility * (facing * light.strength(mag(&to_light)));
+}
+
+/// Bends a hit's normal by its material's normal map, if it has one and the shape knows which way its texture runs.
+/// Texels are in tangent space: red along u, green towards the top of the image, and blue straight out. A texel
+/// pointing straight out leaves the normal exactly as it was.
+/// # Arguements
+/// * 'r' - the hit to shade
+/// * 'textures' - the images materials refer to
+fn apply_normal_map(r: RayHit, textures: &[image::Rgb32FImage]) -> RayHit {
+    let (Some(index), Some((u_dir, v_dir))) = (r.mat.normal_map, r.tangents) else {
+        return r;
+    };
+    let Some(image) = textures.get(index) else {
+        return r;
+    };
+
+    let [x, y, z] = geometry::nearest_pixel(image, r.u, r.v).0;
+    if x == 0.0 && y == 0.0 {
+        return r;
+    }
+
+    // tangent along u, flattened onto the surface. It vanishes where the texture pinches to a point
+    let n = r.surface_normal;
+    let along_u = u_dir - n * (n * u_dir);
+    if mag(&along_u) < 1e-6 {
+        return r;
+    }
+    let tangent = norm(along_u);
+    // green is up the image, which is against v. Mirrored textures flip which side that is
+    let mut bitangent = cross(n, tangent);
+    if bitangent * v_dir > 0.0 {
+        bitangent = bitangent * -1.0;
+    }
+
+    let to_world = Mat3::from_columns(tangent, bitangent, n);
+    return RayHit {
+        surface_normal: norm(to_world * vec(x, y, z)),
+        ..r
+    };
+}
+
+/// Tilts a hit's normal by its material's bumps, if it has any. The bumps are a noise height field fixed in world
+/// space, so they stay put from frame to frame and line up across neighbouring objects.
+fn apply_bump(r: RayHit) -> RayHit {
+    let Some(bump) = r.mat.bump else {
+        return r;
+    };
+    // tilting away from where the height rises is the same as leaning the surface up that slope
+    let n = r.surface_normal;
+    let slope = value_noise_gradient(r.intersect * bump.frequency) * (bump.scale * bump.frequency);
+    let along_surface = slope - n * (n * slope);
+    return RayHit {
+        surface_normal: norm(n - along_surface),
+        ..r
+    };
+}
+
+/// Bends a ray passing through a surface by Snell's law, or mirrors it if it hits the surface too shallowly
+/// to get out (total internal reflection). Works from either side, going by which way the normal faces.
+/// # Arguements
+/// * 'dir' - normalized direction of the incoming ray
+/// * 'normal' - the surface normal, pointing out of the refractive object
+/// * 'ior' - the object's index of refraction
+fn refract(dir: Vec3, normal: Vec3, ior: f32) -> Vec3 {
+    let mut n = normal;
+    let mut eta = 1.0 / ior;
+    let mut cos_in = -(dir * n);
+    if cos_in < 0.0 {
+        // leaving the object, so everything flips
+        n = n * -1.0;
+        eta = ior;
+        cos_in = -cos_in;
+    }
+
+    let k = 1.0 - eta * eta * (1.0 - cos_in * cos_in);
+    if k < 0.0 {
+        return norm(dir + n * (2.0 * cos_in));
+    }
+    return norm(dir * eta + n * (eta * cos_in - f32::sqrt(k)));
+}
+
+/// The highlight at a hit on a glossy surface, each channel from 0 to 1. Tinted by both the light and the material.
+/// # Arguements
+/// * 'r' - the hit being shaded. Its material's shininess sets how tight the highlight is, and anything but a glossy
+///   material has none
+/// * 'light' - the light making the highlight
+/// * 'view_dir' - normalized direction from the hit back towards whoever is looking at it
+/// * 'model' - how the highlight is worked out
+/// * 'visibility' - how much of the light gets past whatever is in the way, same as for diffuse light
+fn specular_calc(
+    r: RayHit,
+    light: Light,
+    view_dir: Vec3,
+    model: SpecularModel,
+    visibility: Vec3,
+) -> Vec3 {
+    let geometry::MaterialType::Glossy {
+        shininess,
+        specular_color,
+        ..
+    } = r.mat.t
+    else {
+        return vec(0.0, 0.0, 0.0);
+    };
+    let surface_norm = r.surface_normal;
+    let pos = r.intersect;
+
+    // vector from point to light, and normalized
+    let to_light = light.target(pos) - pos;
+    let light_dir_norm = norm(to_light);
+
+    // how well the surface lines up to bounce light at the viewer. Facing away counts as 0, since a negative
+    // number to a fractional power is NaN
+    let alignment = match model {
+        SpecularModel::Phong => {
+            // reflection of light vector across surface normal vector, compared with the view
+            let reflect = surface_norm * (surface_norm * light_dir_norm * 2.0) - light_dir_norm;
+            norm(reflect) * view_dir
+        }
+        SpecularModel::Blinn => {
+            // the half vector can still line up with the normal when the light is just behind the surface
+            if surface_norm * light_dir_norm <= 0.0 {
+                return vec(0.0, 0.0, 0.0);
+            }
+            // the normal a mirror would need to bounce the light straight at the viewer, compared with the real one
+            let half = norm(light_dir_norm + view_dir);
+            half * surface_norm
+        }
+    };
+    // clamp values to the reasonable
+    let specular = alignment.max(0.0).powf(shininess).clamp(0.0, 1.0);
+    return mul_elem(
+        // fades with distance just like the diffuse light, so far highlights don't outshine their surface
+        mul_elem(light.color * light.strength(mag(&to_light)), specular_color) * specular,
+        visibility,
+    );
+}
+
+fn read_lines(path: &Path) -> io::Result<io::Lines<BufReader<File>>> {
+    // Open the file in read-only mode.
+    let file = File::open(path)?;
+    // Read the file line by line, and return an iterator of the lines of the file.
+    return Ok(io::BufReader::new(file).lines());
+}
+
+fn parse_vec(string: &str) -> Vec3 {
+    let mut chars = string.chars();
+    chars.next();
+    chars.next_back();
+    let fixed_str = chars.as_str();
+    let mut split = fixed_str.split(' ');
+    let x = split
+        .next()
+        .unwrap_or_default()
+        .parse::<f32>()
+        .unwrap_or(0.0);
+    let y = split
+        .next()
+        .unwrap_or_default()
+        .parse::<f32>()
+        .unwrap_or(0.0);
+    let z = split
+        .next()
+        .unwrap_or_default()
+        .parse::<f32>()
+        .unwrap_or(0.0);
+
+    return vec(x, y, z);
+}
+
+/// Parses a light's `(constant linear quadratic)` falloff. Missing, negative, or all zero terms mean no falloff.
+fn parse_attenuation(string: &str) -> Vec3 {
+    if string.is_empty() {
+        return NO_FALLOFF;
+    }
+    let a = parse_vec(string);
+    if a.x < 0.0 || a.y < 0.0 || a.z < 0.0 || a.x + a.y + a.z == 0.0 {
+        return NO_FALLOFF;
+    }
+    return a;
+}
+
+/// Parses a light's range. Missing or not positive means it reaches everywhere.
+fn parse_range(string: &str) -> Option<f32> {
+    return string.parse::<f32>().ok().filter(|range| *range > 0.0);
+}
+
+/// Parses one side of a csg line, either `sphere:(center):radius` or `box:(min corner):(max corner)`
+fn parse_solid(string: &str) -> Option<Solid> {
+    let mut split = string.split(':');
+    match split.next()? {
+        "sphere" => {
+            let center = parse_vec(split.next()?);
+            let radius = split.next()?.parse::<f32>().ok()?;
+            return Some(Solid::Sphere { center, radius });
+        }
+        "box" => {
+            let a = parse_vec(split.next()?);
+            let b = parse_vec(split.next()?);
+            return Some(Solid::Cuboid {
+                min: vec(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+                max: vec(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+            });
+        }
+        _ => return None,
+    }
+}
+
+/// A parsed triangle as it goes into the scene: moved by the current transform (if any) and culled to match
+/// the scene's setting
+fn place_triangle(tr: Triangle, transform: Option<&Mat4>, cull_backfaces: bool) -> Triangle {
+    let tr = Triangle {
+        cull_backfaces,
+        ..tr
+    };
+    match transform {
+        Some(m) => return transform_triangle(tr, m),
+        None => return tr,
+    }
+}
+
+/// Splits the quad a-b-c-d into the triangles a-b-c and a-c-d, which share a winding and therefore a normal.
+/// Both halves keep the same id so they never shadow or reflect each other.
+/// Fails if the corners don't lie in one plane or don't form a proper (non-crossing) quad.
+fn split_quad(
+    a: Vec3,
+    b: Vec3,
+    c: Vec3,
+    d: Vec3,
+    mat: geometry::Material,
+    id: i8,
+    double_sided: bool,
+) -> Result<[Triangle; 2], String> {
+    let first = cross(b - a, c - a);
+    let second = cross(c - a, d - a);
+
+    if mag(&first) < f32::EPSILON || mag(&second) < f32::EPSILON {
+        return Err("corners are degenerate (zero area)".to_string());
+    }
+
+    let normal = norm(first);
+    let size = f32::max(mag(&(c - a)), mag(&(d - b)));
+    if ((d - a) * normal).abs() > size * 1e-4 {
+        return Err("corners do not lie in one plane".to_string());
+    }
+
+    if norm(second) * normal <= 0.0 {
+        return Err("corners are not in order around the edge".to_string());
+    }
+
+    return Ok([
+        Triangle {
+            a,
+            b,
+            c,
+            vertex_normals: None,
+            uvs: None,
+            double_sided,
+            cull_backfaces: false,
+            mat,
+            id,
+        },
+        Triangle {
+            a,
+            b: c,
+            c: d,
+            vertex_normals: None,
+            uvs: None,
+            double_sided,
+            cull_backfaces: false,
+            mat,
+            id,
+        },
+    ]);
+}
+
+/// What a sphere becomes once it has been transformed
+enum TransformedSphere {
+    Sphere(Sphere),
+    Ellipsoid(Ellipsoid),
+}
+
+/// Places a sphere under a transform. Uniform scales keep it a sphere, while a non-uniform scale turns it
+/// into an ellipsoid as long as there's no rotation involved, since ellipsoids only stretch along the axes.
+fn transform_sphere(s: Sphere, m: &Mat4) -> Result<TransformedSphere, String> {
+    let center = m.transform_point(s.center);
+    let x = m.transform_vector(vec(1.0, 0.0, 0.0));
+    let y = m.transform_vector(vec(0.0, 1.0, 0.0));
+    let z = m.transform_vector(vec(0.0, 0.0, 1.0));
+    let (sx, sy, sz) = (mag(&x), mag(&y), mag(&z));
+    let tolerance = 1e-5 * f32::max(sx, f32::max(sy, sz));
+
+    let orthogonal =
+        (x * y).abs() < tolerance && (y * z).abs() < tolerance && (x * z).abs() < tolerance;
+    if orthogonal && (sx - sy).abs() < tolerance && (sx - sz).abs() < tolerance {
+        return Ok(TransformedSphere::Sphere(Sphere {
+            center,
+            velocity: m.transform_vector(s.velocity),
+            radius: s.radius * sx,
+            ..s
+        }));
+    }
+
+    let axis_aligned = x.y.abs() < tolerance
+        && x.z.abs() < tolerance
+        && y.x.abs() < tolerance
+        && y.z.abs() < tolerance
+        && z.x.abs() < tolerance
+        && z.y.abs() < tolerance;
+    if mag(&s.velocity) > 0.0 {
+        return Err("moving spheres can only be moved, turned, and scaled evenly".to_string());
+    }
+    if axis_aligned && !s.inverted {
+        return Ok(TransformedSphere::Ellipsoid(Ellipsoid {
+            center,
+            radii: vec(s.radius * sx, s.radius * sy, s.radius * sz),
+            mat: s.mat,
+            id: s.id,
+        }));
+    }
+
+    return Err("non-uniform scale on a rotated sphere is not supported".to_string());
+}
+
+/// Parses a texture coordinate pair like `(0.5 1)`. u runs across the image, v down it from the top row.
+fn parse_uv(string: &str) -> (f32, f32) {
+    let uv = parse_vec(string);
+    return (uv.x, uv.y);
+}
+
+/// Parses the `(a):(b):frequency` part of a noise pattern
+fn parse_bands(string: &str) -> geometry::NoiseBands {
+    let mut split = string.split(':');
+    let a = parse_vec(split.next().unwrap_or_default());
+    let b = parse_vec(split.next().unwrap_or_default());
+    let mut frequency = split
+        .next()
+        .unwrap_or_default()
+        .parse::<f32>()
+        .unwrap_or(1.0);
+    if frequency <= 0.0 {
+        frequency = 1.0;
+    }
+    return geometry::NoiseBands { a, b, frequency };
+}
+
+/// What an image's pixels mean, which decides how they're decoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum TextureKind {
+    /// Gamma encoded colors, decoded to linear ones
+    Color,
+    /// Tangent space directions, with 128 as zero, decoded to run from -1 to 1
+    Normal,
+}
+
+/// Images loaded while parsing a scene. A file used by several objects is only loaded once for each way it's used.
+struct TextureCache {
+    images: Vec<image::Rgb32FImage>,
+    /// Where each file went in `images`
+    indices: HashMap<(PathBuf, TextureKind), usize>,
+    /// Gamma color textures are decoded with
+    gamma: f32,
+}
+
+impl TextureCache {
+    fn new(gamma: f32) -> TextureCache {
+        return TextureCache {
+            images: Vec::new(),
+            indices: HashMap::new(),
+            gamma,
+        };
+    }
+
+    /// Loads and decodes an image if it isn't already, and gives back its index in `images`
+    fn load(&mut self, path: PathBuf, kind: TextureKind) -> Result<usize, String> {
+        if let Some(&index) = self.indices.get(&(path.clone(), kind)) {
+            return Ok(index);
+        }
+        let image = match image::open(&path) {
+            Ok(image) => image.to_rgb8(),
+            Err(e) => return Err(format!("could not load {}: {}", path.display(), e)),
+        };
+        if image.width() == 0 || image.height() == 0 {
+            return Err(format!("{} is empty", path.display()));
+        }
+        let gamma = self.gamma;
+        let decoded = image::ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+            let texel = image.get_pixel(x, y).0;
+            match kind {
+                TextureKind::Color => {
+                    return image::Rgb(texel.map(|c| (c as f32 / 255.0).powf(gamma)));
+                }
+                TextureKind::Normal => {
+                    return image::Rgb(
+                        texel.map(|c| ((c as f32 - 128.0) / 127.0).clamp(-1.0, 1.0)),
+                    );
+                }
+            }
+        });
+        self.images.push(decoded);
+        self.indices.insert((path, kind), self.images.len() - 1);
+        return Ok(self.images.len() - 1);
+    }
+}
+
+/// Parses the color field of a line. Either a plain color like `(1 0 0)`, a checkerboard like
+/// `checker:(1 1 1):(0 0 0):0.5` with two colors and the size of each square, an image like `texture:earth.png`, or
+/// a noise pattern like `marble:(0.9 0.9 0.9):(0.3 0.3 0.35):4.0` or `wood:(0.6 0.4 0.2):(0.4 0.25 0.1):3.0` with
+/// two colors and how finely they're banded.
+/// # Arguements
+/// * 'string' - the color field
+/// * 'scene_dir' - folder texture paths are relative to
+/// * 'textures' - images loaded so far, which new ones are added to
+fn parse_albedo(
+    string: &str,
+    scene_dir: &Path,
+    textures: &mut TextureCache,
+) -> Result<Albedo, String> {
+    if let Some(path_str) = string.strip_prefix("texture:") {
+        return Ok(Albedo::Texture(
+            textures.load(scene_dir.join(path_str), TextureKind::Color)?,
+        ));
+    }
+    if let Some(bands) = string.strip_prefix("marble:") {
+        return Ok(Albedo::Marble(parse_bands(bands)));
+    }
+    if let Some(bands) = string.strip_prefix("wood:") {
+        return Ok(Albedo::Wood(parse_bands(bands)));
+    }
+
+    let Some(checker) = string.strip_prefix("checker:") else {
+        return Ok(Albedo::Solid(parse_vec(string)));
+    };
+    let mut split = checker.split(':');
+    let a = parse_vec(split.next().unwrap_or_default());
+    let b = parse_vec(split.next().unwrap_or_default());
+    let mut tile = split
+        .next()
+        .unwrap_or_default()
+        .parse::<f32>()
+        .unwrap_or(1.0);
+    if tile <= 0.0 {
+        tile = 1.0;
+    }
+    // cubes centered on multiples of the tile size, so walls and floors at round numbers
+    // sit in the middle of a layer instead of on a boundary where the colors would speckle
+    let half = tile / 2.0;
+    return Ok(Albedo::Checker(geometry::Checker {
+        a,
+        b,
+        tile,
+        origin: vec(-half, -half, -half),
+    }));
+}
+
+/// Adds one of the extras that can follow a material after a `+`, like `glossy:32+normal:bricks.png`
+/// # Arguements
+/// * 'mat' - the material to change
+/// * 'string' - the modifier. `normal:<path>` adds a tangent space normal map, `bump:<scale>:<frequency>` adds
+///   noisy bumps, `specular:(r g b)` tints highlights, `reflect:<amount>:<roughness>` gives glossy materials a
+///   reflection blurred by roughness, and `shadow:<amount>` sets how much light gets through shadows onto it
+/// * 'scene_dir' - folder image paths are relative to
+/// * 'textures' - images loaded so far, which new ones are added to
+fn apply_modifier(
+    mat: &mut geometry::Material,
+    string: &str,
+    scene_dir: &Path,
+    textures: &mut TextureCache,
+) -> Result<(), String> {
+    let (kind, param) = string.split_once(':').unwrap_or((string, ""));
+    match kind {
+        "normal" => {
+            mat.normal_map = Some(textures.load(scene_dir.join(param), TextureKind::Normal)?)
+        }
+        "bump" => {
+            let mut split = param.split(':');
+            let scale = split
+                .next()
+                .unwrap_or_default()
+                .parse::<f32>()
+                .unwrap_or(0.1);
+            let mut frequency = split
+                .next()
+                .unwrap_or_default()
+                .parse::<f32>()
+                .unwrap_or(1.0);
+            // no bumps at all would be a flat surface with extra steps
+            if frequency <= 0.0 {
+                frequency = 1.0;
+            }
+            mat.bump = Some(geometry::Bump { scale, frequency });
+        }
+        "specular" => match &mut mat.t {
+            geometry::MaterialType::Glossy { specular_color, .. } => {
+                *specular_color = parse_vec(param)
+            }
+            _ => return Err("only glossy materials have highlights to tint".to_string()),
+        },
+        "reflect" => match &mut mat.t {
+            geometry::MaterialType::Glossy {
+                reflectivity,
+                roughness,
+                ..
+            } => {
+                let (amount_str, roughness_str) = param.split_once(':').unwrap_or((param, ""));
+                *reflectivity = amount_str.parse::<f32>().unwrap_or(0.5).clamp(0.0, 1.0);
+                *roughness = roughness_str.parse::<f32>().unwrap_or(0.0).clamp(0.0, 1.0);
+            }
+            _ => {
+                return Err(
+                    "only glossy materials take a reflection, use refl for mirrors".to_string(),
+                )
+            }
+        },
+        "shadow" => match param.parse::<f32>() {
+            Ok(amount) => mat.shadow = Some(amount.clamp(0.0, 1.0)),
+            Err(_) => return Err(format!("invalid shadow amount {:?}", param)),
+        },
+        _ => return Err(format!("unknown modifier {:?}", kind)),
+    }
+    return Ok(());
+}
+
+/// Parses the material field of a line, like `matte`, `glossy:64`, `glossy:64:blinn`, `refl:0.35`, `refl:1:0.2`,
+/// `refr:1.5`, `refr:1.5:(0.4 0.1 0.1)` with how much of each channel is absorbed per unit inside, or `emit:3.0`,
+/// into a material of the given color.
+/// Anything unrecognized is matte.
+fn parse_material(albedo: Albedo, string: &str) -> geometry::Material {
+    let mut split = string.split(':');
+    let kind = split.next().unwrap_or_default();
+    let param = split.next().unwrap_or_default();
+
+    let t = match kind {
+        "glossy" => {
+            // 0 or less would make highlights cover everything, or go infinite
+            let mut shininess = param.parse::<f32>().unwrap_or(DEFAULT_SHININESS);
+            if shininess <= 0.0 {
+                shininess = DEFAULT_SHININESS;
+            }
+            let specular = match split.next().unwrap_or_default() {
+                "phong" => Some(SpecularModel::Phong),
+                "blinn" => Some(SpecularModel::Blinn),
+                _ => None,
+            };
+            geometry::MaterialType::Glossy {
+                albedo,
+                shininess,
+                specular,
+                specular_color: WHITE,
+                reflectivity: 0.0,
+                roughness: 0.0,
+            }
+        }
+        "refl" => geometry::MaterialType::Reflective {
+            tint: albedo,
+            // a plain mirror unless told otherwise
+            reflectivity: param.parse::<f32>().unwrap_or(1.0).clamp(0.0, 1.0),
+            // sharp unless told otherwise, and a sphere wider than 1 would mostly point backwards
+            fuzz: split
+                .next()
+                .unwrap_or_default()
+                .parse::<f32>()
+                .unwrap_or(0.0)
+                .clamp(0.0, 1.0),
+        },
+        "refr" => {
+            // ordinary glass unless told otherwise
+            let mut ior = param.parse::<f32>().unwrap_or(1.5);
+            if ior <= 0.0 {
+                ior = 1.5;
+            }
+            // clear unless told otherwise. Absorbing a negative amount would make light out of nothing
+            let absorption = match split.next() {
+                Some(absorption_str) => {
+                    let a = parse_vec(absorption_str);
+                    vec(a.x.max(0.0), a.y.max(0.0), a.z.max(0.0))
+                }
+                None => CLEAR,
+            };
+            geometry::MaterialType::Refractive {
+                tint: albedo,
+                ior,
+                absorption,
+            }
+        }
+        "emit" => geometry::MaterialType::Emissive {
+            color: albedo,
+            // a negative glow would just be black
+            intensity: param.parse::<f32>().unwrap_or(1.0).max(0.0),
+        },
+        _ => geometry::MaterialType::Matte { albedo },
+    };
+    return geometry::Material::new(t);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads a scene written out from `text`, rendered quietly unless the settings say otherwise
+    fn scene(name: &str, text: &str, settings: RenderSettings) -> Scene {
+        let path =
+            std::env::temp_dir().join(format!("raytracer_{}_{}.ray", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let scene = Scene::from_ray_file(
+            &path,
+            RenderSettings {
+                quiet: true,
+                ..settings
+            },
+        );
+        std::fs::remove_file(&path).unwrap();
+        return scene.unwrap();
+    }
+
+    fn render(scene: &Scene, width: u32, height: u32) -> image::RgbImage {
+        return scene.develop(&scene.render(width, height, &mut |_| {}).image);
+    }
+
+    #[test]
+    fn every_accel_renders_the_same() {
+        // test.ray's spheres and walls, plus one of most other shapes and a cloud of small spheres to sort through
+        let mut text = std::fs::read_to_string("test.ray").unwrap();
+        text.push_str(
+            "\nlight,(4 6 -2)\n\
+             torus,(3 -1 -9),(0.3 1 0.2),1.2,0.3,(0.8 0.6 0.2),glossy,20\n\
+             ellipsoid,(-1 -1.2 -7),(1.2 0.5 0.7),(0.2 0.7 0.7),matte,21\n\
+             cylinder,(4.5 -2 -13),(0 1 0),0.6,3,(0.6 0.6 0.9),refl:0.5,22\n\
+             ground,-2,(0.9 0.9 0.9),(0.2 0.2 0.2),1\n",
+        );
+        for i in 0..60 {
+            let (x, y) = ((i % 10) as f32 - 4.5, (i / 10) as f32);
+            text.push_str(&format!(
+                "sphere,({} {} -{}),0.3,(0.{} 0.5 0.5),{},{}\n",
+                x * 1.2,
+                y * 1.1 - 1.0,
+                12.0 + (i % 3) as f32,
+                i % 10,
+                if i % 4 == 0 { "refl" } else { "matte" },
+                30 + i
+            ));
+        }
+
+        let (width, height) = (48, 36);
+        let renders: Vec<image::RgbImage> = [Accel::Bvh, Accel::None, Accel::Grid]
+            .into_iter()
+            .map(|accel| {
+                let settings = RenderSettings {
+                    accel,
+                    ..RenderSettings::default()
+                };
+                let scene = scene(&format!("accel_{:?}", accel), &text, settings);
+                return render(&scene, width, height);
+            })
+            .collect();
+
+        assert!(renders[0].pixels().any(|p| p.0 != [0, 0, 0]));
+        for (render, name) in renders[1..].iter().zip(["--no-bvh", "--accel=grid"]) {
+            let differing = renders[0]
+                .pixels()
+                .zip(render.pixels())
+                .filter(|(a, b)| a != b)
+                .count();
+            assert_eq!(differing, 0, "{} pixels differ with {}", differing, name);
+        }
+    }
+
+    #[test]
+    fn textured_quad_shows_the_image_undistorted() {
+        // every texel its own color, so any stretch, flip or swap of u and v shows
+        let texels = 8;
+        let texture = image::RgbImage::from_fn(texels, texels, |x, y| {
+            image::Rgb([x as u8 * 32, y as u8 * 32, 128])
+        });
+        let texture_name = format!("raytracer_quad_{}.png", std::process::id());
+        let texture_path = std::env::temp_dir().join(&texture_name);
+        texture.save(&texture_path).unwrap();
+
+        // the frame is 2 units across at 2 units out, so a 4 unit quad at 4 units out exactly fills the picture.
+        // Glowing, each pixel is just the texel behind it
+        let text = format!(
+            "camera,(0 0 0),(0 0 -1)\n\
+             uvtriangle,(-2 -2 -4),(2 -2 -4),(2 2 -4),(0 1),(1 1),(1 0),texture:{0},emit,1\n\
+             uvtriangle,(-2 -2 -4),(2 2 -4),(-2 2 -4),(0 1),(1 0),(0 0),texture:{0},emit,2\n",
+            texture_name
+        );
+        let scene = scene("textured_quad", &text, RenderSettings::default());
+        std::fs::remove_file(&texture_path).unwrap();
+
+        let size = 32;
+        let img = render(&scene, size, size);
+        let scale = size / texels;
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let expected = texture.get_pixel(x / scale, y / scale);
+            for (a, b) in pixel.0.iter().zip(expected.0) {
+                assert!(
+                    a.abs_diff(b) <= 1,
+                    "({}, {}) is {:?}, expected {:?}",
+                    x,
+                    y,
+                    pixel,
+                    expected
+                );
+            }
+        }
+    }
+
+    fn brightness(pixel: &image::Rgb<u8>) -> u32 {
+        return pixel.0.iter().map(|&c| c as u32).sum();
+    }
+
+    #[test]
+    fn glossy_highlight_has_no_dark_ring() {
+        // lit from the camera, so the highlight is right in the middle and adds up well past 1
+        let scene = scene(
+            "glossy_highlight",
+            "camera,(0 0 0),(0 0 -5)\nlight,(0 0 0)\nsphere,(0 0 -5),1.5,(1 1 1),glossy,0\n",
+            RenderSettings::default(),
+        );
+        let (width, height) = (41, 41);
+        let img = render(&scene, width, height);
+        let middle = img.get_pixel(width / 2, height / 2);
+        assert_eq!(middle.0, [255, 255, 255]);
+
+        // from the middle out to the edge of the sphere, nothing gets brighter again
+        let y = height / 2;
+        for x in width / 2..width - 1 {
+            let (inner, outer) = (img.get_pixel(x, y), img.get_pixel(x + 1, y));
+            assert!(
+                brightness(outer) <= brightness(inner),
+                "{:?} at x = {} is brighter than {:?} inside it",
+                outer,
+                x + 1,
+                inner
+            );
+        }
+    }
+
+    #[test]
+    fn equirect_edges_meet_behind_the_camera() {
+        let camera =
+            Camera::look_at(vec(0.0, 0.0, 0.0), vec(0.0, 0.0, -1.0), vec(0.0, 1.0, 0.0)).unwrap();
+        let (width, height) = (64, 32);
+        let pixel_width = 2.0 / height as f32;
+        let aspect = width as f32 / height as f32;
+        let direction = |x: f32, y: f32| {
+            return get_ray(x, y, &camera, Projection::Equirect, pixel_width, aspect)
+                .unwrap()
+                .direction_vector;
+        };
+        for y in 0..height {
+            let y = y as f32;
+            // the left edge of the first column and the right edge of the last are the same direction
+            let left = direction(-0.5, y);
+            let right = direction(width as f32 - 0.5, y);
+            assert!(mag(&(left - right)) < 1e-5, "{:?} and {:?}", left, right);
+
+            // and the middles of those columns mirror each other either side of straight behind
+            let first = direction(0.0, y);
+            let last = direction(width as f32 - 1.0, y);
+            assert!((first.x + last.x).abs() < 1e-5);
+            assert!((first.y - last.y).abs() < 1e-5);
+            assert!((first.z - last.z).abs() < 1e-5);
+            assert!(first.z > 0.0);
+        }
+        // nothing breaks at the poles
+        for y in [-0.5, 0.0, height as f32 - 1.0, height as f32 - 0.5] {
+            for x in [0.0, width as f32 / 2.0, width as f32 - 1.0] {
+                let d = direction(x, y);
+                assert!(d.x.is_finite() && d.y.is_finite() && d.z.is_finite());
+            }
+        }
+    }
+
+    #[test]
+    fn equirect_first_and_last_columns_match() {
+        // everything is mirrored left to right, with a sphere straddling the seam behind the camera
+        let scene = scene(
+            "equirect_seam",
+            "camera,(0 0 0),(0 0 -1)\nlight,(0 5 0)\nsphere,(0 0 5),2,(0.8 0.3 0.3),matte,0\n\
+             sphere,(0 -3 4),1,(0.3 0.3 0.8),glossy,1\nsphere,(0 0 -6),1,(0.3 0.8 0.3),matte,2\n",
+            RenderSettings {
+                projection: Projection::Equirect,
+                ..RenderSettings::default()
+            },
+        );
+        let (width, height) = (64, 32);
+        let img = render(&scene, width, height);
+        for y in 0..height {
+            let (first, last) = (img.get_pixel(0, y), img.get_pixel(width - 1, y));
+            for (a, b) in first.0.iter().zip(last.0) {
+                assert!(a.abs_diff(b) <= 1, "row {}: {:?} and {:?}", y, first, last);
+            }
+        }
+        // the sphere behind really is in both
+        assert_ne!(img.get_pixel(0, height / 2).0, [0, 0, 0]);
+    }
+
+    #[test]
+    fn quarter_roll_turns_the_picture() {
+        // lopsided, so any wrong turn or flip shows
+        let text = "camera,(0 0 0),(0 0 -1)\nlight,(2 4 0)\nsphere,(1.5 0.5 -8),1,(0.8 0.3 0.3),matte,0\n\
+                    sphere,(-0.5 -1 -6),0.7,(0.3 0.3 0.8),glossy,1\nsphere,(0 2 -9),0.5,(0.3 0.8 0.3),matte,2\n";
+        let size = 48;
+        let level = render(
+            &scene("roll_0", text, RenderSettings::default()),
+            size,
+            size,
+        );
+        let rolled = RenderSettings {
+            roll: Some(90.0),
+            ..RenderSettings::default()
+        };
+        let rolled = render(&scene("roll_90", text, rolled), size, size);
+
+        // rolling the camera anticlockwise turns the picture clockwise
+        let turned = image::imageops::rotate90(&level);
+        let mut differing = 0;
+        for (a, b) in rolled.pixels().zip(turned.pixels()) {
+            if a.0.iter().zip(b.0).any(|(a, b)| a.abs_diff(b) > 1) {
+                differing += 1;
+            }
+        }
+        assert_eq!(differing, 0);
+        // and it isn't just matching because it's empty
+        assert!(level.pixels().any(|p| p.0 != [0, 0, 0]));
+        assert_ne!(level, turned);
+    }
+
+    #[test]
+    fn zero_roll_changes_nothing() {
+        let camera =
+            Camera::look_at(vec(1.0, 2.0, 3.0), vec(-2.0, 0.5, -4.0), vec(0.0, 1.0, 0.0)).unwrap();
+        let same = camera.with_roll(0.0);
+        assert_eq!(mag(&(same.right - camera.right)), 0.0);
+        assert_eq!(mag(&(same.up - camera.up)), 0.0);
+        assert_eq!(mag(&(same.forward - camera.forward)), 0.0);
+    }
+
+    #[test]
+    fn glossy_highlights_share_the_shadow_rays() {
+        let shadow_rays = |material: &str| {
+            let scene = scene(
+                &format!("shared_shadows_{}", material),
+                &format!(
+                    "camera,(0 0 0),(0 0 -5)\nlight,(2 2 0)\nsphere,(0 0 -5),3,(1 1 1),{},0\n",
+                    material
+                ),
+                RenderSettings::default(),
+            );
+            render(&scene, 16, 16);
+            return scene.world.stats.get(Counter::Shadow);
+        };
+        // a glossy surface is lit by the same light as a matte one, so it needs no more rays to find out how much
+        assert!(shadow_rays("matte") > 0);
+        assert_eq!(shadow_rays("glossy"), shadow_rays("matte"));
+    }
+
+    #[test]
+    fn glass_tints_what_is_seen_through_it() {
+        // with an index of 1 the rays go straight through, so all that changes the white sky is the tint
+        let scene = scene(
+            "tinted_glass",
+            "camera,(0 0 0),(0 0 -5)\nbackground,(1 1 1)\nsphere,(0 0 -5),1.5,(1 0 0),refr,1\n",
+            RenderSettings::default(),
+        );
+        let (width, height) = (9, 9);
+        let img = render(&scene, width, height);
+        let middle = img.get_pixel(width / 2, height / 2);
+        assert_eq!(middle.0, [255, 0, 0]);
+        assert_eq!(img.get_pixel(0, 0).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn coverage_and_surfaces_come_from_the_camera_rays() {
+        let scene = scene(
+            "camera_ray_passes",
+            "camera,(0 0 0),(0 0 -5)\nsphere,(0 0 -5),1.5,(1 1 1),matte,0\n",
+            RenderSettings {
+                samples: 4,
+                denoise: true,
+                ..RenderSettings::default()
+            },
+        );
+        let (width, height) = (16, 16);
+        let render = scene.render(width, height, &mut |_| {});
+        // the denoiser and the extra images don't send out any camera rays of their own
+        assert_eq!(
+            scene.world.stats.get(Counter::Primary),
+            (width * height * 4) as u64
+        );
+        let middle = (height / 2 * width + width / 2) as usize;
+        assert_eq!(render.coverage[middle], 1.0);
+        assert_eq!(render.surfaces[middle].map(|surface| surface.id), Some(0));
+        assert_eq!(render.coverage[0], 0.0);
+        assert!(render.surfaces[0].is_none());
+    }
+
+    #[test]
+    fn glowing_spheres_light_from_where_they_are_placed() {
+        let scene = scene(
+            "placed_emitters",
+            "light,(0 10 0)\n\
+             push_transform,(0 0 -10),(0 0 0),(2 2 2)\n\
+             sphere,(1 0 0),0.5,(1 1 1),emit,0\n\
+             pop_transform\n\
+             push_transform,(0 0 0),(0 45 0),(1 2 1)\n\
+             sphere,(0 0 -5),0.5,(1 1 1),emit,1\n\
+             pop_transform\n\
+             sphere_moving,(-4 0 -5),0.5,(1 1 1),emit,2,(2 0 0)\n",
+            RenderSettings::default(),
+        );
+        // the sphere that couldn't be placed doesn't leave its light behind
+        let positions: Vec<Vec3> = scene.lights[1..].iter().map(|l| l.position).collect();
+        assert_eq!(positions.len(), 2);
+        let close = |a: Vec3, b: Vec3| mag(&(a - b)) < 1e-4;
+        assert!(
+            close(positions[0], vec(2.0, 0.0, -10.0)),
+            "{:?}",
+            positions[0]
+        );
+        // halfway through the shutter
+        assert!(
+            close(positions[1], vec(-3.0, 0.0, -5.0)),
+            "{:?}",
+            positions[1]
+        );
+    }
+}
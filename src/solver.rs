@@ -0,0 +1,197 @@
+// Closed form polynomial root finders, done in f64 since the quartic loses precision fast in f32.
+// Based on Jochen Schwarze's solvers from Graphics Gems I.
+
+/// Anything closer to zero than this is treated as zero
+const EQN_EPS: f64 = 1e-9;
+
+fn is_zero(x: f64) -> bool {
+    return x.abs() < EQN_EPS;
+}
+
+/// Real roots of a*x^2 + b*x + c = 0
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if is_zero(a) {
+        if is_zero(b) {
+            return Vec::new();
+        }
+        return vec![-c / b];
+    }
+
+    // normal form: x^2 + px + q = 0
+    let p = b / (2.0 * a);
+    let q = c / a;
+    let discriminant = p * p - q;
+
+    if is_zero(discriminant) {
+        return vec![-p];
+    } else if discriminant < 0.0 {
+        return Vec::new();
+    }
+
+    let root = f64::sqrt(discriminant);
+    return vec![root - p, -root - p];
+}
+
+/// Real roots of a*x^3 + b*x^2 + c*x + d = 0
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if is_zero(a) {
+        return solve_quadratic(b, c, d);
+    }
+
+    // normal form: x^3 + Ax^2 + Bx + C = 0
+    let big_a = b / a;
+    let big_b = c / a;
+    let big_c = d / a;
+
+    // substitute x = y - A/3 to eliminate the quadric term: y^3 + 3py + 2q = 0
+    let sq_a = big_a * big_a;
+    let p = (1.0 / 3.0) * (-(1.0 / 3.0) * sq_a + big_b);
+    let q = 0.5 * ((2.0 / 27.0) * big_a * sq_a - (1.0 / 3.0) * big_a * big_b + big_c);
+
+    // use Cardano's formula
+    let cb_p = p * p * p;
+    let discriminant = q * q + cb_p;
+
+    let mut roots = if is_zero(discriminant) {
+        if is_zero(q) {
+            // one triple solution
+            vec![0.0]
+        } else {
+            // one single and one double solution
+            let u = f64::cbrt(-q);
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        // three real solutions
+        let phi = (1.0 / 3.0) * f64::acos(-q / f64::sqrt(-cb_p));
+        let t = 2.0 * f64::sqrt(-p);
+        vec![
+            t * f64::cos(phi),
+            -t * f64::cos(phi + std::f64::consts::PI / 3.0),
+            -t * f64::cos(phi - std::f64::consts::PI / 3.0),
+        ]
+    } else {
+        // one real solution
+        let sqrt_d = f64::sqrt(discriminant);
+        vec![f64::cbrt(sqrt_d - q) - f64::cbrt(sqrt_d + q)]
+    };
+
+    // resubstitute
+    let sub = (1.0 / 3.0) * big_a;
+    for root in roots.iter_mut() {
+        *root -= sub;
+    }
+
+    return roots;
+}
+
+/// Real roots of a*x^4 + b*x^3 + c*x^2 + d*x + e = 0, polished with a couple of Newton steps
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    if is_zero(a) {
+        return solve_cubic(b, c, d, e);
+    }
+
+    // normal form: x^4 + Ax^3 + Bx^2 + Cx + D = 0
+    let big_a = b / a;
+    let big_b = c / a;
+    let big_c = d / a;
+    let big_d = e / a;
+
+    // substitute x = y - A/4 to eliminate the cubic term: y^4 + py^2 + qy + r = 0
+    let sq_a = big_a * big_a;
+    let p = -(3.0 / 8.0) * sq_a + big_b;
+    let q = (1.0 / 8.0) * sq_a * big_a - 0.5 * big_a * big_b + big_c;
+    let r =
+        -(3.0 / 256.0) * sq_a * sq_a + (1.0 / 16.0) * sq_a * big_b - 0.25 * big_a * big_c + big_d;
+
+    let mut roots = if is_zero(r) {
+        // no absolute term: y(y^3 + py + q) = 0
+        let mut roots = solve_cubic(1.0, 0.0, p, q);
+        roots.push(0.0);
+        roots
+    } else {
+        // solve the resolvent cubic and take its one guaranteed real root
+        let z = solve_cubic(1.0, -0.5 * p, -r, 0.5 * r * p - (1.0 / 8.0) * q * q)[0];
+
+        // and use it to build two quadratics
+        let mut u = z * z - r;
+        let mut v = 2.0 * z - p;
+
+        if is_zero(u) {
+            u = 0.0;
+        } else if u > 0.0 {
+            u = f64::sqrt(u);
+        } else {
+            return Vec::new();
+        }
+
+        if is_zero(v) {
+            v = 0.0;
+        } else if v > 0.0 {
+            v = f64::sqrt(v);
+        } else {
+            return Vec::new();
+        }
+
+        let mut roots = solve_quadratic(1.0, if q < 0.0 { -v } else { v }, z - u);
+        roots.append(&mut solve_quadratic(
+            1.0,
+            if q < 0.0 { v } else { -v },
+            z + u,
+        ));
+        roots
+    };
+
+    // resubstitute, then clean up the precision the closed form loses near double roots
+    let sub = 0.25 * big_a;
+    for root in roots.iter_mut() {
+        *root -= sub;
+
+        for _i in 0..2 {
+            let x = *root;
+            let f = (((a * x + b) * x + c) * x + d) * x + e;
+            let df = ((4.0 * a * x + 3.0 * b) * x + 2.0 * c) * x + d;
+            if !is_zero(df) {
+                *root = x - f / df;
+            }
+        }
+    }
+
+    return roots;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut roots: Vec<f64>) -> Vec<f64> {
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        return roots;
+    }
+
+    #[test]
+    fn quartic_four_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        let roots = sorted(solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0));
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+            assert!((root - expected).abs() < 1e-6, "{:?}", roots);
+        }
+    }
+
+    #[test]
+    fn quartic_no_roots() {
+        // x^4 + 1
+        assert!(solve_quartic(1.0, 0.0, 0.0, 0.0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn quartic_double_root() {
+        // (x - 2)^2 (x^2 + 1) only touches zero, at 2
+        let roots = solve_quartic(1.0, -4.0, 5.0, -4.0, 4.0);
+        assert!(!roots.is_empty());
+        for root in roots {
+            assert!((root - 2.0).abs() < 1e-4, "{}", root);
+        }
+    }
+}
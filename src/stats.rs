@@ -0,0 +1,121 @@
+// Counts of the work done in a render, kept as atomics so every render thread can add to them without taking a lock,
+// plus how long each stage took.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Something counted while rendering
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Counter {
+    /// Rays from the camera
+    Primary,
+    /// Rays toward lights
+    Shadow,
+    /// Rays off mirrors and glossy surfaces
+    Reflection,
+    /// Rays through glass
+    Refraction,
+    /// Rays bounced off matte surfaces when path tracing
+    Bounce,
+    /// Rays testing for ambient occlusion
+    Occlusion,
+    /// Rays tested against single objects. Only counted with detailed stats, since there are many per ray
+    IntersectionTest,
+}
+
+const COUNTERS: usize = 7;
+
+#[derive(Debug, Default)]
+pub struct RenderStats {
+    counts: [AtomicU64; COUNTERS],
+    /// Whether intersection tests are counted too
+    pub detailed: bool,
+}
+
+/// How long each stage of making an image took
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Timings {
+    pub parsing: Duration,
+    pub rendering: Duration,
+    pub encoding: Duration,
+}
+
+impl RenderStats {
+    pub fn count(&self, counter: Counter) {
+        if counter == Counter::IntersectionTest && !self.detailed {
+            return;
+        }
+        self.counts[counter as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, counter: Counter) -> u64 {
+        return self.counts[counter as usize].load(Ordering::Relaxed);
+    }
+
+    /// Rays cast after the first hit, on average for each pixel of the image
+    fn bounces_per_pixel(&self, pixels: u64) -> f64 {
+        let bounces = self.get(Counter::Reflection)
+            + self.get(Counter::Refraction)
+            + self.get(Counter::Bounce);
+        return bounces as f64 / pixels.max(1) as f64;
+    }
+
+    /// The counts and timings written out for people, a few lines long
+    /// # Arguements
+    /// * 'pixels' - how many pixels the image has
+    /// * 'timings' - how long each stage took
+    pub fn report(&self, pixels: u64, timings: &Timings) -> String {
+        let mut report = format!(
+            "Rays: {} primary, {} shadow, {} reflection, {} refraction, {} bounce, {} occlusion\n",
+            self.get(Counter::Primary),
+            self.get(Counter::Shadow),
+            self.get(Counter::Reflection),
+            self.get(Counter::Refraction),
+            self.get(Counter::Bounce),
+            self.get(Counter::Occlusion)
+        );
+        report += &format!("Bounces per pixel: {:.2}\n", self.bounces_per_pixel(pixels));
+        if self.detailed {
+            report += &format!(
+                "Intersection tests: {}\n",
+                self.get(Counter::IntersectionTest)
+            );
+        }
+        report += &format!(
+            "Time: {:.3}s parsing, {:.3}s rendering, {:.3}s encoding",
+            timings.parsing.as_secs_f64(),
+            timings.rendering.as_secs_f64(),
+            timings.encoding.as_secs_f64()
+        );
+        return report;
+    }
+
+    /// The counts and timings as a JSON object. Intersection tests are null unless they were counted
+    /// # Arguements
+    /// * 'pixels' - how many pixels the image has
+    /// * 'timings' - how long each stage took
+    pub fn to_json(&self, pixels: u64, timings: &Timings) -> String {
+        let intersection_tests = if self.detailed {
+            self.get(Counter::IntersectionTest).to_string()
+        } else {
+            "null".to_string()
+        };
+        return format!(
+            "{{\n  \"primary_rays\": {},\n  \"shadow_rays\": {},\n  \"reflection_rays\": {},\n  \
+             \"refraction_rays\": {},\n  \"bounce_rays\": {},\n  \"occlusion_rays\": {},\n  \
+             \"bounces_per_pixel\": {},\n  \"intersection_tests\": {},\n  \"parsing_seconds\": {},\n  \
+             \"rendering_seconds\": {},\n  \"encoding_seconds\": {}\n}}\n",
+            self.get(Counter::Primary),
+            self.get(Counter::Shadow),
+            self.get(Counter::Reflection),
+            self.get(Counter::Refraction),
+            self.get(Counter::Bounce),
+            self.get(Counter::Occlusion),
+            self.bounces_per_pixel(pixels),
+            intersection_tests,
+            timings.parsing.as_secs_f64(),
+            timings.rendering.as_secs_f64(),
+            timings.encoding.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,101 @@
+// Splits an image into square tiles that render threads take one at a time, so a thread that gets an easy patch of
+// sky just takes another tile instead of sitting idle while another works through a hall of mirrors.
+
+use crate::progress::Progress;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// A rectangle of pixels. Tiles on the right and bottom edges are cut short to fit the image
+#[derive(Debug, Copy, Clone)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Cuts an image into tiles, starting from the middle and spiralling out, so the subject usually comes first
+/// # Arguements
+/// * 'width' - the image's width in pixels
+/// * 'height' - the image's height in pixels
+/// * 'size' - how wide and tall each tile is, in pixels
+pub fn spiral_tiles(width: u32, height: u32, size: u32) -> Vec<Tile> {
+    let size = size.max(1);
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(size as usize) {
+        for x in (0..width).step_by(size as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: size.min(width - x),
+                height: size.min(height - y),
+            });
+        }
+    }
+
+    // ring by ring out from the middle, going round each ring in order of angle
+    let middle = (width as f32 / 2.0, height as f32 / 2.0);
+    let place = |tile: &Tile| {
+        let dx = (tile.x as f32 + tile.width as f32 / 2.0 - middle.0) / size as f32;
+        let dy = (tile.y as f32 + tile.height as f32 / 2.0 - middle.1) / size as f32;
+        let ring = dx.abs().max(dy.abs()).round();
+        return (ring, f32::atan2(dy, dx));
+    };
+    tiles.sort_by(|a, b| place(a).partial_cmp(&place(b)).unwrap());
+    return tiles;
+}
+
+/// Works out every pixel of an image, with each of rayon's threads taking the next tile off the queue until there are
+/// none left
+/// # Arguements
+/// * 'width' - the image's width in pixels
+/// * 'height' - the image's height in pixels
+/// * 'size' - how wide and tall each tile is, in pixels
+/// * 'progress' - told about each tile's pixels as it's finished
+/// * 'pixel' - works out the pixel at a column and row
+pub fn render_tiles<T, F>(
+    width: u32,
+    height: u32,
+    size: u32,
+    progress: &Progress,
+    pixel: F,
+) -> Vec<T>
+where
+    T: Copy + Send,
+    F: Fn(u32, u32) -> T + Sync,
+{
+    let tiles = spiral_tiles(width, height, size);
+    let next = AtomicUsize::new(0);
+    let done = Mutex::new(Vec::with_capacity(tiles.len()));
+    rayon::scope(|s| {
+        for _ in 0..rayon::current_num_threads() {
+            s.spawn(|_| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(&tile) = tiles.get(i) else {
+                    break;
+                };
+                let mut pixels = Vec::with_capacity((tile.width * tile.height) as usize);
+                for y in tile.y..tile.y + tile.height {
+                    for x in tile.x..tile.x + tile.width {
+                        pixels.push(pixel(x, y));
+                    }
+                }
+                done.lock().unwrap().push((tile, pixels));
+                progress.advance((tile.width * tile.height) as usize);
+            });
+        }
+    });
+
+    // stitched back together in image order
+    let mut image = vec![None; (width * height) as usize];
+    for (tile, pixels) in done.into_inner().unwrap() {
+        for (row, line) in pixels.chunks(tile.width as usize).enumerate() {
+            let start = ((tile.y + row as u32) * width + tile.x) as usize;
+            for (slot, &pixel) in image[start..start + line.len()].iter_mut().zip(line) {
+                *slot = Some(pixel);
+            }
+        }
+    }
+    // the tiles cover the image without overlapping, so every pixel has been filled in exactly once
+    return image.into_iter().map(Option::unwrap).collect();
+}